@@ -4,27 +4,36 @@
 //
 use core::{str::FromStr};
 
-use heapless::{String, Vec};
-use fuga_json_seq_parser::{JsonScalarValue, ParserCallbackAction, JsonNode, JsonNumber};
-use fuga_json_seq_parser::Parser as JsonParser;
+use heapless::Vec;
+use fuga_json_seq_parser::{JsonScalarValue, ParserCallbackAction, JsonNode};
 use fuga_json_seq_parser::ParserError as JsonParserError;
 
 use uuid::Uuid;
 use crate::{config::*, Device};
 use crate::common_types::*;
 use crate::node_key::*;
+use crate::location::{CountingReader, PathTracker, ReadError};
 use crate::device::MacAddress;
-use crate::parser_options::{ParserOptions, copy_string_option};
+use crate::parser_options::{ParserOptions, is_string};
+use crate::statistics::ReadStatistics;
+use crate::numeric::coerce_number;
+use crate::schema::{
+    ViolationKind, APPLIANCE_KEYS, DEVICE_KEYS, MODEL_KEYS, PROPERTY_KEYS, REQUIRED_APPLIANCE_FIELDS, REQUIRED_DEVICE_FIELDS, REQUIRED_MODEL_FIELDS,
+    REQUIRED_PROPERTY_FIELDS, SMART_METER_KEYS,
+};
 
 #[derive(Clone, Debug, Default, PartialEq)]
-pub struct Appliance {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Appliance<C: Capacities = DefaultCapacities> {
     pub id: Uuid,
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
     pub type_: ApplianceType,
-    pub nickname: String<MAX_NICKNAME_LEN>,
-    pub image: String<MAX_IMAGE_LEN>,
+    pub nickname: C::Nickname,
+    pub image: C::Image,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum ApplianceType {
     AC,
     TV,
@@ -38,8 +47,11 @@ pub enum ApplianceType {
     StorageBattery,
     QrioLock,
     MorninPlus,
+    /// A type not known by the parser, set in lenient mode. Written as `UNKNOWN`, which only serde reads back.
+    Unknown,
     //Custom(String<16>),
 }
+#[allow(clippy::derivable_impls)]
 impl Default for ApplianceType {
     fn default() -> Self {
         Self::AC
@@ -68,34 +80,55 @@ impl<'a> TryFrom<&'a str> for ApplianceType {
     }
 }
 
+impl ApplianceType {
+    /// Type name in Remo Cloud API.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::AC => "AC",
+            Self::TV => "TV",
+            Self::Light => "LIGHT",
+            Self::IR => "IR",
+            Self::SmartMeter => "EL_SMART_METER",
+            Self::ElectricWaterHeater => "EL_ELECTRIC_WATER_HEATER",
+            Self::PowerDistMeter => "EL_POWER_DIST_METER",
+            Self::EVCD => "EL_EVCD",
+            Self::SolarPower => "EL_SOLAR_POWER",
+            Self::StorageBattery => "EL_STORAGE_BATTERY",
+            Self::QrioLock => "QRIO_LOCK",
+            Self::MorninPlus => "MORNIN_PLUS",
+            Self::Unknown => "UNKNOWN",
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default, PartialEq)]
-pub struct EchonetLiteProperty {
-    pub name: String<MAX_ECHONET_LITE_NAME_LEN>,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EchonetLiteProperty<C: Capacities = DefaultCapacities> {
+    pub name: C::EchonetLiteName,
     pub epc: u32,
-    pub val: String<MAX_ECHONET_LITE_VALUE_LEN>,
+    pub val: C::EchonetLiteValue,
     pub updated_at: Timestamp,
 }
 
 #[derive(Clone, Debug, Default, PartialEq)]
-pub struct ApplianceModel {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ApplianceModel<C: Capacities = DefaultCapacities> {
     pub id: Uuid,
-    pub country: String<MAX_COUNTRY_LEN>,
-    pub manufacturer: String<MAX_MANUFACTURER_LEN>,
-    pub remote_name: String<MAX_REMOTE_NAME_LEN>,
-    pub series: String<MAX_SERIES_LEN>,
-    pub name: String<MAX_MODEL_NAME_LEN>,
-    pub image: String<MAX_IMAGE_LEN>,
+    pub country: C::Country,
+    pub manufacturer: C::Manufacturer,
+    pub remote_name: C::RemoteName,
+    pub series: C::Series,
+    pub name: C::ModelName,
+    pub image: C::Image,
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub enum ApplianceSubNode {
-    Device(Device),
-    Model(ApplianceModel),
-    EchonetLiteProperty(EchonetLiteProperty),
+pub enum ApplianceSubNode<C: Capacities = DefaultCapacities> {
+    Device(Device<C>),
+    Model(ApplianceModel<C>),
+    EchonetLiteProperty(EchonetLiteProperty<C>),
 }
 
-type AppliancesParser = JsonParser<REQUIRED_APPLIANCES_PARSER_BUFFER_LEN, 10>;
-
 #[derive(Clone, Copy, Debug)]
 enum AppliancesParserState {
     Start,
@@ -110,6 +143,7 @@ enum AppliancesParserState {
     UnknownArray,
 }
 impl AppliancesParserState {
+    #[allow(clippy::match_like_matches_macro)]
     fn is_map_state(&self) -> bool {
         match self {
             Self::ApplianceMap => true,
@@ -121,6 +155,10 @@ impl AppliancesParserState {
             _ => false,
         }
     }
+    fn is_unknown_state(&self) -> bool {
+        matches!(self, Self::UnknownMap | Self::UnknownArray)
+    }
+    #[allow(clippy::match_like_matches_macro)]
     fn is_array_state(&self) -> bool {
         match self {
             Self::AppliancesArray => true,
@@ -129,55 +167,114 @@ impl AppliancesParserState {
             _ => false,
         }
     }
+    /// Keys known in the map of this state.
+    fn keys(&self) -> FieldMask {
+        match self {
+            Self::ApplianceMap => APPLIANCE_KEYS,
+            Self::DeviceMap => DEVICE_KEYS,
+            Self::ModelMap => MODEL_KEYS,
+            Self::SmartMeterMap => SMART_METER_KEYS,
+            Self::EchonetLitePropertyMap => PROPERTY_KEYS,
+            _ => FieldMask::ALL,
+        }
+    }
 }
 
+// The error carries its location in bounded storage, as it cannot be boxed without alloc.
+#[allow(clippy::result_large_err)]
 pub fn read_appliances<R: embedded_io::blocking::Read, F>(
     reader: &mut R,
     total_length: Option<usize>,
     options: &ParserOptions,
-    mut callback: F,
-) -> Result<(), JsonParserError<R::Error, ModelNodeParseError>>
+    callback: F,
+) -> Result<ReadStatistics, ReadError<R::Error>>
 where
     F: for<'a> FnMut(&'a Appliance, Option<&'a ApplianceSubNode>),
 {
-    let mut parser = AppliancesParser::new();
+    read_appliances_with_capacities::<DefaultCapacities, _, _>(reader, total_length, options, callback)
+}
+
+/// `read_appliances` with the string capacities and the parser of `C`.
+#[allow(clippy::result_large_err)]
+pub fn read_appliances_with_capacities<C: Capacities, R: embedded_io::blocking::Read, F>(
+    reader: &mut R,
+    total_length: Option<usize>,
+    options: &ParserOptions,
+    mut callback: F,
+) -> Result<ReadStatistics, ReadError<R::Error>>
+where
+    F: for<'a> FnMut(&'a Appliance<C>, Option<&'a ApplianceSubNode<C>>),
+{
+    let mut parser = C::AppliancesParser::new();
     parser.set_bytes_remaining(total_length);
-    let mut appliance = Appliance::default();
+    let mut reader = CountingReader::new(reader);
+    let mut path = PathTracker::new();
+    let mut appliance = Appliance::<C>::default();
     let mut subnode = ApplianceSubNode::Device(Device::default());
     let mut state = AppliancesParserState::Start;
     let mut node_key = None;
     let mut state_stack: Vec<AppliancesParserState, 10> = Vec::new();
+    let mut index = 0;  // Index of the current appliance.
+    let mut statistics = ReadStatistics::default();
+    // Keys seen in the current appliance map and sub node map, and the number of violations in strict mode.
+    let mut seen = FieldMask::NONE;
+    let mut sub_seen = FieldMask::NONE;
+    let mut violations = 0;
 
-    while !parser.parse(reader, |node| {
+    while !parser.parse(&mut reader, |node| {
+        path.update(&node);
         let new_state = match (state, node) {
             // Start array
             (state, JsonNode::StartArray) => {
                 state_stack.push(state).map_err(|_| ModelNodeParseError::NodeTooDeep)?;
-                match (state, node_key.take()) {
+                match (state, node_key.take().filter(|key| options.is_selected(*key))) {
                     (AppliancesParserState::Start, _) => AppliancesParserState::AppliancesArray,
                     (AppliancesParserState::SmartMeterMap, Some(ModelNodeKey::EchonetLiteProperties)) => AppliancesParserState::EchonetLitePropertiesArray,
-                    (_, _)=> AppliancesParserState::UnknownArray,
+                    (_, key)=> {
+                        if !state.is_unknown_state() {
+                            statistics.skipped_containers += 1;
+                            if let Some(key) = key {
+                                options.report_violation(ViolationKind::WrongType(key), path.path(), &mut violations);
+                            }
+                        }
+                        AppliancesParserState::UnknownArray
+                    },
                 }
             },
             // Start map
             (state, JsonNode::StartMap) => {
                 state_stack.push(state).map_err(|_| ModelNodeParseError::NodeTooDeep)?;
-                match (state, node_key.take()) {
-                    (AppliancesParserState::AppliancesArray, _) => AppliancesParserState::ApplianceMap,
+                match (state, node_key.take().filter(|key| options.is_selected(*key))) {
+                    (AppliancesParserState::AppliancesArray, _) => {
+                        seen = FieldMask::NONE;
+                        appliance = Appliance::default();
+                        AppliancesParserState::ApplianceMap
+                    },
                     (AppliancesParserState::ApplianceMap, Some(ModelNodeKey::Device)) => {
+                        sub_seen = FieldMask::NONE;
                         subnode = ApplianceSubNode::Device(Device::default());
                         AppliancesParserState::DeviceMap
                     },
                     (AppliancesParserState::ApplianceMap, Some(ModelNodeKey::Model)) => {
+                        sub_seen = FieldMask::NONE;
                         subnode = ApplianceSubNode::Model(ApplianceModel::default());
                         AppliancesParserState::ModelMap
                     },
                     (AppliancesParserState::ApplianceMap, Some(ModelNodeKey::SmartMeter)) => AppliancesParserState::SmartMeterMap,
                     (AppliancesParserState::EchonetLitePropertiesArray, _) => {
+                        sub_seen = FieldMask::NONE;
                         subnode = ApplianceSubNode::EchonetLiteProperty(EchonetLiteProperty::default());
                         AppliancesParserState::EchonetLitePropertyMap
                     }
-                    (_, _)=> AppliancesParserState::UnknownMap,
+                    (_, key)=> {
+                        if !state.is_unknown_state() {
+                            statistics.skipped_containers += 1;
+                            if let Some(key) = key {
+                                options.report_violation(ViolationKind::WrongType(key), path.path(), &mut violations);
+                            }
+                        }
+                        AppliancesParserState::UnknownMap
+                    },
                 }
             },
             // End array
@@ -186,6 +283,16 @@ where
             },
             // End map
             (state, JsonNode::EndMap) if state.is_map_state() => {
+                let required = match state {
+                    AppliancesParserState::ApplianceMap => Some((REQUIRED_APPLIANCE_FIELDS, seen)),
+                    AppliancesParserState::DeviceMap => Some((REQUIRED_DEVICE_FIELDS, sub_seen)),
+                    AppliancesParserState::ModelMap => Some((REQUIRED_MODEL_FIELDS, sub_seen)),
+                    AppliancesParserState::EchonetLitePropertyMap => Some((REQUIRED_PROPERTY_FIELDS, sub_seen)),
+                    _ => None,
+                };
+                if let Some((required, seen)) = required {
+                    options.check_required(required, seen, path.path(), &mut violations);
+                }
                 let (dont_invoke_callback, is_subnode) = match state {
                     AppliancesParserState::UnknownMap => (true, true),
                     AppliancesParserState::SmartMeterMap => (true, true),
@@ -196,17 +303,33 @@ where
                     // Invoke callback
                     if is_subnode {
                         callback(&appliance, Some(&subnode));
+                        statistics.sub_nodes += 1;
                     } else {
                         callback(&appliance, None);
-
+                        statistics.elements += 1;
+                        index += 1;
                     }
                 }
                 state_stack.pop().ok_or(ModelNodeParseError::UnexpectedMapArrayEnd)?
             },
             (map_state, JsonNode::Key(key)) => {
+                #[allow(clippy::single_match)]
                 match key {
                     JsonScalarValue::String(key) => {
-                        node_key = ModelNodeKey::try_from(key).ok(); // Store key
+                        node_key = ModelNodeKey::try_from(key).ok().filter(|key| map_state.keys().contains(*key)); // Store key
+                        match (node_key, map_state) {
+                            (Some(key), AppliancesParserState::ApplianceMap) => seen = seen.with(key),
+                            (Some(key), AppliancesParserState::DeviceMap | AppliancesParserState::ModelMap | AppliancesParserState::EchonetLitePropertyMap) => {
+                                sub_seen = sub_seen.with(key)
+                            }
+                            (None, state) if !state.is_unknown_state() => {
+                                path.mark_unknown_key();
+                                statistics.unknown_keys += 1;
+                                let unmodeled = matches!(state, AppliancesParserState::ApplianceMap);
+                                options.report_unknown_key(&path, key, unmodeled, &mut violations);
+                            }
+                            _ => {}
+                        }
                     }
                     _ => {} // Unknown key.
                 }
@@ -218,60 +341,82 @@ where
                     ApplianceSubNode::Device(ref mut device) => device,
                     _ => { return Err(ModelNodeParseError::UnexpectedParserState); },
                 };
-                if let Some(node_key) = node_key.take() {
+                if let Some(node_key) = options.take_key(&mut node_key, &value) {
                     match (node_key, value) {
-                        (ModelNodeKey::Name, JsonScalarValue::String(s)) => {
-                            device.name = copy_string_option(s, options)?;
+                        (ModelNodeKey::Name, value) if is_string(&value) => {
+                            options.copy_piece(&mut device.name, node_key, index, value, &mut statistics)?;
                         }
                         (ModelNodeKey::Id, JsonScalarValue::String(s)) => {
-                            device.id = Uuid::from_str(s)?
+                            device.id = options.recover_field(node_key, index, parse_uuid(s))?
                         }
                         (ModelNodeKey::CreatedAt, JsonScalarValue::String(s)) => {
-                            device.created_at = Timestamp::from_str(s)?
+                            device.created_at = options.recover_field(node_key, index, Timestamp::from_str(s))?
                         }
                         (ModelNodeKey::UpdatedAt, JsonScalarValue::String(s)) => {
-                            device.updated_at = Timestamp::from_str(s)?
+                            device.updated_at = options.recover_field(node_key, index, Timestamp::from_str(s))?
                         }
                         (ModelNodeKey::MacAddress, JsonScalarValue::String(s)) => {
-                            device.mac_address = MacAddress::from_str(s)?
+                            device.mac_address = options.recover_field(node_key, index, MacAddress::from_str(s))?
                         }
                         (ModelNodeKey::BtMacAddress, JsonScalarValue::String(s)) => {
-                            device.bt_mac_address = MacAddress::from_str(s)?
+                            device.bt_mac_address = options.recover_field(node_key, index, MacAddress::from_str(s))?
                         }
-                        (ModelNodeKey::SerialNumber, JsonScalarValue::String(s)) => {
-                            device.serial_number = copy_string_option(s, options)?;
+                        (ModelNodeKey::SerialNumber, value) if is_string(&value) => {
+                            options.copy_piece(&mut device.serial_number, node_key, index, value, &mut statistics)?;
                         }
-                        (ModelNodeKey::FirmwareVersion, JsonScalarValue::String(s)) => {
-                            device.firmware_version = copy_string_option(s, options)?;
+                        (ModelNodeKey::FirmwareVersion, value) if is_string(&value) => {
+                            options.copy_piece(&mut device.firmware_version, node_key, index, value, &mut statistics)?;
                         }
-                        (ModelNodeKey::TemperatureOffset, JsonScalarValue::Number(n)) => {
-                            device.temperature_offset = n.into()
+                        // Following pieces of a string longer than the parser buffer.
+                        (_, JsonScalarValue::ContinuingString(_, offset, _)) if offset > 0 => {}
+                        (ModelNodeKey::Id | ModelNodeKey::CreatedAt | ModelNodeKey::UpdatedAt | ModelNodeKey::MacAddress | ModelNodeKey::BtMacAddress, JsonScalarValue::ContinuingString(..)) => {
+                            options.recover_field(node_key, index, Err::<(), _>(ModelNodeParseError::StringTooLong))?
                         }
-                        (ModelNodeKey::HumidityOffset, JsonScalarValue::Number(n)) => {
-                            device.humidity_offset = n.into()
+                        (ModelNodeKey::TemperatureOffset | ModelNodeKey::HumidityOffset, value) => {
+                            options.check_number(node_key, &value, path.path(), &mut violations);
+                            let offset = options.recover_field(node_key, index, coerce_number(&value))?;
+                            if node_key == ModelNodeKey::TemperatureOffset {
+                                device.temperature_offset = offset;
+                            } else {
+                                device.humidity_offset = offset;
+                            }
                         }
-                        _ => {} // Ignore unknown nodes.
+                        _ => options.report_violation(ViolationKind::WrongType(node_key), path.path(), &mut violations),
                     }
                 }
                 AppliancesParserState::DeviceMap
             }
             // Appliance map
             (AppliancesParserState::ApplianceMap, JsonNode::Value(value)) => {
-                if let Some(node_key) = node_key.take() {
+                if let Some(node_key) = options.take_key(&mut node_key, &value) {
                     match (node_key, value) {
-                        (ModelNodeKey::NickName, JsonScalarValue::String(s)) => {
-                            appliance.nickname = copy_string_option(s, options)?;
+                        (ModelNodeKey::NickName, value) if is_string(&value) => {
+                            options.copy_piece(&mut appliance.nickname, node_key, index, value, &mut statistics)?;
                         }
                         (ModelNodeKey::Id, JsonScalarValue::String(s)) => {
-                            appliance.id = Uuid::from_str(s)?
+                            appliance.id = options.recover_field(node_key, index, parse_uuid(s))?
                         }
                         (ModelNodeKey::Type, JsonScalarValue::String(s)) => {
-                            appliance.type_ = ApplianceType::try_from(s).or(Err(ModelNodeParseError::UnexpectedEnumValue))?;
+                            appliance.type_ = options.recover_field_or(
+                                node_key,
+                                index,
+                                ApplianceType::try_from(s).or(Err(ModelNodeParseError::UnexpectedEnumValue)),
+                                ApplianceType::Unknown,
+                            )?;
                         }
-                        (ModelNodeKey::Image, JsonScalarValue::String(s)) => {
-                            appliance.image = copy_string_option(s, options)?;
+                        (ModelNodeKey::Image, value) if is_string(&value) => {
+                            options.copy_piece(&mut appliance.image, node_key, index, value, &mut statistics)?;
                         }
-                        _ => {} // Ignore unknown nodes.
+                        // Following pieces of a string longer than the parser buffer.
+                        (_, JsonScalarValue::ContinuingString(_, offset, _)) if offset > 0 => {}
+                        (ModelNodeKey::Id, JsonScalarValue::ContinuingString(..)) => {
+                            options.recover_field(node_key, index, Err::<(), _>(ModelNodeParseError::StringTooLong))?
+                        }
+                        (ModelNodeKey::Type, JsonScalarValue::ContinuingString(..)) => {
+                            appliance.type_ = options.recover_field_or(node_key, index, Err(ModelNodeParseError::UnexpectedEnumValue), ApplianceType::Unknown)?;
+                        }
+                        (ModelNodeKey::Model, JsonScalarValue::Null) => {} // Appliances without a model.
+                        _ => options.report_violation(ViolationKind::WrongType(node_key), path.path(), &mut violations),
                     }
                 }
                 AppliancesParserState::ApplianceMap
@@ -282,30 +427,35 @@ where
                     ApplianceSubNode::Model(ref mut model) => model,
                     _ => { return Err(ModelNodeParseError::UnexpectedParserState); },
                 };
-                if let Some(node_key) = node_key.take() {
+                if let Some(node_key) = options.take_key(&mut node_key, &value) {
                     match (node_key, value) {
-                        (ModelNodeKey::Name, JsonScalarValue::String(s)) => {
-                            model.name = copy_string_option(s, options)?;
+                        (ModelNodeKey::Name, value) if is_string(&value) => {
+                            options.copy_piece(&mut model.name, node_key, index, value, &mut statistics)?;
                         }
                         (ModelNodeKey::Id, JsonScalarValue::String(s)) => {
-                            model.id = Uuid::from_str(s)?
+                            model.id = options.recover_field(node_key, index, parse_uuid(s))?
+                        }
+                        (ModelNodeKey::Country, value) if is_string(&value) => {
+                            options.copy_piece(&mut model.country, node_key, index, value, &mut statistics)?;
                         }
-                        (ModelNodeKey::Country, JsonScalarValue::String(s)) => {
-                            model.country = copy_string_option(s, options)?;
+                        (ModelNodeKey::Manufacturer, value) if is_string(&value) => {
+                            options.copy_piece(&mut model.manufacturer, node_key, index, value, &mut statistics)?;
                         }
-                        (ModelNodeKey::Manufacturer, JsonScalarValue::String(s)) => {
-                            model.manufacturer = copy_string_option(s, options)?;
+                        (ModelNodeKey::RemoteName, value) if is_string(&value) => {
+                            options.copy_piece(&mut model.remote_name, node_key, index, value, &mut statistics)?;
                         }
-                        (ModelNodeKey::RemoteName, JsonScalarValue::String(s)) => {
-                            model.remote_name = copy_string_option(s, options)?;
+                        (ModelNodeKey::Series, value) if is_string(&value) => {
+                            options.copy_piece(&mut model.series, node_key, index, value, &mut statistics)?;
                         }
-                        (ModelNodeKey::Series, JsonScalarValue::String(s)) => {
-                            model.series = copy_string_option(s, options)?;
+                        (ModelNodeKey::Image, value) if is_string(&value) => {
+                            options.copy_piece(&mut model.image, node_key, index, value, &mut statistics)?;
                         }
-                        (ModelNodeKey::Image, JsonScalarValue::String(s)) => {
-                            model.image = copy_string_option(s, options)?;
+                        // Following pieces of a string longer than the parser buffer.
+                        (_, JsonScalarValue::ContinuingString(_, offset, _)) if offset > 0 => {}
+                        (ModelNodeKey::Id, JsonScalarValue::ContinuingString(..)) => {
+                            options.recover_field(node_key, index, Err::<(), _>(ModelNodeParseError::StringTooLong))?
                         }
-                        _ => {} // Ignore unknown nodes.
+                        _ => options.report_violation(ViolationKind::WrongType(node_key), path.path(), &mut violations),
                     }
                 }
                 AppliancesParserState::ModelMap
@@ -316,21 +466,27 @@ where
                     ApplianceSubNode::EchonetLiteProperty(ref mut property) => property,
                     _ => { return Err(ModelNodeParseError::UnexpectedParserState); },
                 };
-                if let Some(node_key) = node_key.take() {
+                if let Some(node_key) = options.take_key(&mut node_key, &value) {
                     match (node_key, value) {
-                        (ModelNodeKey::Name, JsonScalarValue::String(s)) => {
-                            property.name = copy_string_option(s, options)?;
+                        (ModelNodeKey::Name, value) if is_string(&value) => {
+                            options.copy_piece(&mut property.name, node_key, index, value, &mut statistics)?;
                         }
-                        (ModelNodeKey::Epc, JsonScalarValue::Number(JsonNumber::I32(n))) => {
-                            property.epc = n as u32;
+                        // Following pieces of a string longer than the parser buffer.
+                        (_, JsonScalarValue::ContinuingString(_, offset, _)) if offset > 0 => {}
+                        (ModelNodeKey::UpdatedAt, JsonScalarValue::ContinuingString(..)) => {
+                            options.recover_field(node_key, index, Err::<(), _>(ModelNodeParseError::StringTooLong))?
                         }
-                        (ModelNodeKey::Val, JsonScalarValue::String(s)) => {
-                            property.val = copy_string_option(s, options)?;
+                        (ModelNodeKey::Epc, value) => {
+                            options.check_number(node_key, &value, path.path(), &mut violations);
+                            property.epc = options.recover_field(node_key, index, coerce_number(&value))?;
+                        }
+                        (ModelNodeKey::Val, value) if is_string(&value) => {
+                            options.copy_piece(&mut property.val, node_key, index, value, &mut statistics)?;
                         }
                         (ModelNodeKey::UpdatedAt, JsonScalarValue::String(s)) => {
-                            property.updated_at = Timestamp::from_str(s)?;
+                            property.updated_at = options.recover_field(node_key, index, Timestamp::from_str(s))?;
                         }
-                        _ => {} // Ignore unknown nodes.
+                        _ => options.report_violation(ViolationKind::WrongType(node_key), path.path(), &mut violations),
                     }
                 }
                 AppliancesParserState::EchonetLitePropertyMap
@@ -353,17 +509,29 @@ where
         };
         state = new_state;
         Ok(ParserCallbackAction::Nothing)
-    })? {}
-    Ok(())
+    }).map_err(|error| path.error(error, reader.bytes_read()))? {}
+    if violations > 0 {
+        return Err(path.error(JsonParserError::Callback(ModelNodeParseError::SchemaViolation), reader.bytes_read()));
+    }
+    statistics.bytes_read = reader.bytes_read();
+    statistics.max_depth = path.max_depth();
+    Ok(statistics)
 }
 
 #[cfg(test)]
 mod test {
     use fuga_json_seq_parser::BufferReader;
+    use fuga_json_seq_parser::ParserError as JsonParserError;
+    use crate::diagnostics::{DiagnosticKind, Diagnostics};
+    use crate::unknown_keys::{UnknownKeyKind, UnknownKeys};
+    use crate::schema::{ViolationKind, Violations};
+    use crate::location::PathSegment;
+    use heapless::String;
     use uuid::uuid;
 
     use super::*;
 
+    #[allow(clippy::needless_as_bytes)]
     fn create_reader<'a>(input: &'a str) -> (usize, BufferReader<'a>) {
         let total_length = input.as_bytes().len();
         (total_length, BufferReader::new(input.as_bytes()))
@@ -498,4 +666,189 @@ mod test {
         )
         .unwrap();
     }
+
+    #[test]
+    fn test_parse_appliances_with_fields() {
+        let (length, mut reader) = create_reader(include_str!("../data/appliances.json"));
+        let fields = FieldMask::ALL
+            .without(ModelNodeKey::Device)
+            .without(ModelNodeKey::Model)
+            .without(ModelNodeKey::UpdatedAt);
+        let options = ParserOptions::default().with_fields(fields);
+        let mut appliances = 0;
+        let mut properties = 0;
+        let statistics = read_appliances(&mut reader, Some(length), &options, |appliance, sub_node| match sub_node {
+            None => appliances += 1,
+            Some(ApplianceSubNode::EchonetLiteProperty(property)) => {
+                assert_eq!(appliance.type_, ApplianceType::SmartMeter);
+                assert_eq!(property.updated_at, Timestamp::default());
+                properties += 1;
+            }
+            Some(sub_node) => panic!("unselected sub node - {:?}", sub_node),
+        })
+        .unwrap();
+        assert_eq!((appliances, properties), (2, 4));
+        assert_eq!((statistics.elements, statistics.sub_nodes), (appliances, properties));
+        assert_eq!(statistics.bytes_read, length);
+    }
+
+    #[test]
+    fn test_parse_appliances_lenient() {
+        let input = r#"[
+            {"id":"84875896-9f1e-44df-9f49-7989352eeecf","device":{"name":"remo","mac_address":"invalid"},"type":"AC","nickname":"ac"},
+            {"id":"081c5163-ee9e-486e-ba4d-e86a16ea4c9b","type":"NEW_APPLIANCE","nickname":"meter",
+             "smart_meter":{"echonetlite_properties":[{"name":"coefficient","epc":211,"val":"1","updated_at":"yesterday"}]}}
+        ]"#;
+        let (length, mut reader) = create_reader(input);
+        let error = read_appliances(&mut reader, Some(length), &ParserOptions::default(), |_, _| {});
+        let error = error.unwrap_err();
+        assert!(matches!(error.error, JsonParserError::Callback(ModelNodeParseError::MacAddressParseError)));
+        assert_eq!(error.location.index(), Some(0));
+        assert_eq!(error.location.path.last(), Some(&PathSegment::Key(ModelNodeKey::MacAddress)));
+
+        let diagnostics = Diagnostics::<4>::new();
+        let options = ParserOptions::default().lenient(&diagnostics);
+        let (length, mut reader) = create_reader(input);
+        let mut nicknames: Vec<String<MAX_NICKNAME_LEN>, 2> = Vec::new();
+        let mut types: Vec<ApplianceType, 2> = Vec::new();
+        read_appliances(&mut reader, Some(length), &options, |appliance, sub_node| {
+            if sub_node.is_none() {
+                nicknames.push(appliance.nickname.clone()).unwrap();
+                types.push(appliance.type_).unwrap();
+            }
+        })
+        .unwrap();
+        assert_eq!(nicknames.as_slice(), &[String::<MAX_NICKNAME_LEN>::from("ac"), String::from("meter")]);
+        assert_eq!(types.as_slice(), &[ApplianceType::AC, ApplianceType::Unknown]);
+        let diagnostics = diagnostics.take();
+        let summary: Vec<_, 4> = diagnostics.iter().map(|d| (d.key, d.index)).collect();
+        assert_eq!(
+            summary.as_slice(),
+            &[(ModelNodeKey::MacAddress, 0), (ModelNodeKey::Type, 1), (ModelNodeKey::UpdatedAt, 1)]
+        );
+        assert!(matches!(diagnostics[2].kind, DiagnosticKind::Invalid(ModelNodeParseError::TimestampParseError(_))));
+    }
+
+    #[test]
+    fn test_parse_appliances_unknown_keys() {
+        let input = r#"[
+            {"id":"84875896-9f1e-44df-9f49-7989352eeecf","device":{"name":"remo","new_field":1},"tags":[{"x":1}]},
+            {"id":"081c5163-ee9e-486e-ba4d-e86a16ea4c9b","device":{"name":"remo","new_field":2},
+             "smart_meter":{"echonetlite_properties":[{"epc":211,"unit":"kWh"}]}}
+        ]"#;
+        let (length, mut reader) = create_reader(input);
+        let unknown_keys = UnknownKeys::<2, 8>::new();
+        let options = ParserOptions::default().with_unknown_keys(&unknown_keys);
+        let statistics = read_appliances(&mut reader, Some(length), &options, |_, _| {}).unwrap();
+        assert_eq!(statistics.unknown_keys, 4);
+        assert_eq!(unknown_keys.dropped(), 1);
+        let unknown_keys = unknown_keys.take();
+        assert!(unknown_keys.iter().all(|unknown_key| unknown_key.kind == UnknownKeyKind::Unknown));
+        let mut formatted: Vec<String<48>, 2> = Vec::new();
+        for unknown_key in unknown_keys.iter() {
+            let mut s = String::new();
+            core::fmt::Write::write_fmt(&mut s, format_args!("{}", unknown_key)).unwrap();
+            formatted.push(s).unwrap();
+        }
+        assert_eq!(formatted.as_slice(), &[String::<48>::from("[0].device.new_fiel"), String::from("[0].tags")]);
+    }
+
+    #[test]
+    fn test_parse_appliances_strict() {
+        // Fields of air conditioners and signals are not modeled by the parser, but they are in the API.
+        // They are still reported as unmodeled unknown keys.
+        let violations = Violations::<8>::new();
+        let unknown_keys = UnknownKeys::<8, 16>::new();
+        let options = ParserOptions::default().strict(&violations).with_unknown_keys(&unknown_keys);
+        let (length, mut reader) = create_reader(include_str!("../data/appliances.json"));
+        let statistics = read_appliances(&mut reader, Some(length), &options, |_, _| {}).unwrap();
+        assert!(violations.is_empty());
+        assert_eq!(statistics.unknown_keys, 6);
+        let unknown_keys = unknown_keys.take();
+        assert!(unknown_keys.iter().all(|unknown_key| unknown_key.kind == UnknownKeyKind::Unmodeled));
+        assert!(unknown_keys.iter().any(|unknown_key| unknown_key.key == "settings"));
+        assert!(unknown_keys.iter().any(|unknown_key| unknown_key.key == "signals"));
+        // Without the unmodeled keys, they are unknown keys in strict mode.
+        let violations = Violations::<8>::new();
+        let options = ParserOptions::default().strict(&violations).with_unmodeled_keys(&["aircon", "signals"]);
+        let (length, mut reader) = create_reader(include_str!("../data/appliances.json"));
+        read_appliances(&mut reader, Some(length), &options, |_, _| {}).unwrap_err();
+        assert!(!violations.is_empty());
+        assert!(violations.take().iter().all(|violation| violation.kind == ViolationKind::UnknownKey));
+
+        let input = r#"[{"id":"081c5163-ee9e-486e-ba4d-e86a16ea4c9b","type":"EL_SMART_METER","nickname":"meter","image":"ico",
+            "smart_meter":{"echonetlite_properties":[{"name":"coefficient","epc":"211","val":"1","updated_at":"2022-11-18T06:42:59Z"}]}}]"#;
+        let (length, mut reader) = create_reader(input);
+        read_appliances(&mut reader, Some(length), &options, |_, _| {}).unwrap_err();
+        let violations = violations.take();
+        assert_eq!(violations.len(), 2);
+        assert_eq!(violations[0].kind, ViolationKind::WrongType(ModelNodeKey::Epc));
+        assert_eq!(violations[0].path.last(), Some(&PathSegment::Key(ModelNodeKey::Epc)));
+        assert_eq!(violations[1].kind, ViolationKind::MissingField(ModelNodeKey::Device));
+        assert_eq!(violations[1].index(), Some(0));
+    }
+
+    #[test]
+    fn test_parse_appliances_misplaced_keys() {
+        // `epc` is known in ECHONET Lite properties and `series` in models.
+        let input = r#"[{"id":"081c5163-ee9e-486e-ba4d-e86a16ea4c9b","type":"EL_SMART_METER","nickname":"meter","image":"ico","epc":"211",
+            "device":{"id":"f262cb0c-a853-47bb-9559-44d0f2c4d6e4","name":"remo","series":"x"}}]"#;
+        let unknown_keys = UnknownKeys::<4, 8>::new();
+        let (length, mut reader) = create_reader(input);
+        let statistics = read_appliances(&mut reader, Some(length), &ParserOptions::default().with_unknown_keys(&unknown_keys), |_, _| {}).unwrap();
+        assert_eq!(statistics.unknown_keys, 2);
+        let keys: Vec<_, 4> = unknown_keys.take().iter().map(|unknown_key| unknown_key.key.clone()).collect();
+        assert_eq!(keys.as_slice(), &["epc", "series"]);
+
+        let violations = Violations::<16>::new();
+        let (length, mut reader) = create_reader(input);
+        read_appliances(&mut reader, Some(length), &ParserOptions::default().strict(&violations), |_, _| {}).unwrap_err();
+        let violations = violations.take();
+        let unknown: Vec<_, 16> = violations.iter().filter(|violation| violation.kind == ViolationKind::UnknownKey).collect();
+        assert_eq!(unknown.len(), 2);
+        assert!(unknown.iter().all(|violation| violation.path.last() == Some(&PathSegment::UnknownKey)));
+        assert!(!violations.iter().any(|violation| matches!(violation.kind, ViolationKind::WrongType(_))));
+    }
+
+    #[test]
+    fn test_parse_appliances_truncated_strings() {
+        // The nickname and the model name are longer than the parser buffer.
+        let input = r#"[{"id":"84875896-9f1e-44df-9f49-7989352eeecf","type":"AC","nickname":"リビングルームの窓際に置いてあるエアコンディショナー",
+            "model":{"name":"Daikin Air Conditioner for the living room with a very long name and a series","manufacturer":"DAIKIN"}}]"#;
+        let (length, mut reader) = create_reader(input);
+        let mut manufacturer = String::<MAX_MANUFACTURER_LEN>::new();
+        let statistics = read_appliances(&mut reader, Some(length), &ParserOptions::default(), |_, sub_node| {
+            if let Some(ApplianceSubNode::Model(model)) = sub_node {
+                manufacturer = model.manufacturer.clone();
+            }
+        })
+        .unwrap();
+        assert_eq!(manufacturer.as_str(), "DAIKIN");
+        assert_eq!(statistics.truncated_strings, 2);
+    }
+
+    #[test]
+    fn test_parse_appliances_numeric_epc() {
+        let input = r#"[{"id":"081c5163-ee9e-486e-ba4d-e86a16ea4c9b","type":"EL_SMART_METER","smart_meter":{"echonetlite_properties":[
+            {"epc":211.0},{"epc":"212"},{"epc":null},{"epc":4294967296}]}}]"#;
+        let (length, mut reader) = create_reader(input);
+        let error = read_appliances(&mut reader, Some(length), &ParserOptions::default(), |_, _| {}).unwrap_err();
+        assert!(matches!(error.error, JsonParserError::Callback(ModelNodeParseError::InvalidNumber)));
+        assert_eq!(error.location.path.last(), Some(&PathSegment::Key(ModelNodeKey::Epc)));
+
+        let diagnostics = Diagnostics::<2>::new();
+        let (length, mut reader) = create_reader(input);
+        let mut epcs: Vec<u32, 4> = Vec::new();
+        read_appliances(&mut reader, Some(length), &ParserOptions::default().lenient(&diagnostics), |_, sub_node| {
+            if let Some(ApplianceSubNode::EchonetLiteProperty(property)) = sub_node {
+                epcs.push(property.epc).unwrap();
+            }
+        })
+        .unwrap();
+        assert_eq!(epcs.as_slice(), &[211, 212, 0, 0]);
+        let diagnostics = diagnostics.take();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].kind, DiagnosticKind::Invalid(ModelNodeParseError::InvalidNumber));
+        assert_eq!(diagnostics[1].kind, DiagnosticKind::Invalid(ModelNodeParseError::NumberOutOfRange));
+    }
 }
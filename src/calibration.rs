@@ -0,0 +1,167 @@
+// Temperature and humidity offsets of a device.
+// Copyright 2022-2023 Kenta Ida 
+// SPDX-License-Identifier: MIT
+//
+use crate::config::Capacities;
+use crate::device::{Device, NewestEvents, SensorValue};
+
+/// Offsets of the temperature and humidity sensors, as `temperature_offset` and `humidity_offset` of `Device`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SensorOffsets {
+    /// Offset in degrees Celsius.
+    pub temperature: f32,
+    /// Offset in percent of relative humidity.
+    pub humidity: f32,
+}
+
+impl SensorValue {
+    /// The value with `offset` added. `created_at` is kept.
+    pub fn offset_by(&self, offset: f32) -> SensorValue {
+        SensorValue {
+            val: self.val + offset,
+            created_at: self.created_at,
+        }
+    }
+}
+
+/// Whether the values of `NewestEvents` include the offsets configured to the device.
+///
+/// The Remo Cloud API does not document whether `newest_events` reports the raw sensor values
+/// or the values with `temperature_offset` and `humidity_offset` applied, so the caller chooses the convention
+/// to convert between raw and offset-adjusted values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OffsetConvention {
+    /// The reported values already include the offsets, i.e. `reported = raw + offset`.
+    AlreadyApplied,
+    /// The reported values are the raw sensor values, i.e. `reported = raw`.
+    NotApplied,
+}
+
+/// Conversion between raw and offset-adjusted sensor values, under the `OffsetConvention` of the reported values.
+impl<C: Capacities> Device<C> {
+    pub fn offsets(&self) -> SensorOffsets {
+        SensorOffsets {
+            temperature: self.temperature_offset,
+            humidity: self.humidity_offset,
+        }
+    }
+
+    /// Temperature measured by the sensor without the offset.
+    pub fn raw_temperature(&self, events: &NewestEvents, convention: OffsetConvention) -> Option<SensorValue> {
+        events.temperature.as_ref().map(|v| raw_value(v, self.temperature_offset, convention))
+    }
+
+    /// Humidity measured by the sensor without the offset.
+    pub fn raw_humidity(&self, events: &NewestEvents, convention: OffsetConvention) -> Option<SensorValue> {
+        events.humidity.as_ref().map(|v| raw_value(v, self.humidity_offset, convention))
+    }
+
+    /// Temperature with the offset of the device applied.
+    pub fn adjusted_temperature(&self, events: &NewestEvents, convention: OffsetConvention) -> Option<SensorValue> {
+        events.temperature.as_ref().map(|v| adjusted_value(v, self.temperature_offset, convention))
+    }
+
+    /// Humidity with the offset of the device applied.
+    pub fn adjusted_humidity(&self, events: &NewestEvents, convention: OffsetConvention) -> Option<SensorValue> {
+        events.humidity.as_ref().map(|v| adjusted_value(v, self.humidity_offset, convention))
+    }
+
+    /// Compute offsets which make the adjusted values match the reference readings taken at the same time as `events`.
+    /// The current offset is kept for a sensor without its reference reading or its value in `events`.
+    pub fn calibrate(
+        &self,
+        events: &NewestEvents,
+        convention: OffsetConvention,
+        reference_temperature: Option<f32>,
+        reference_humidity: Option<f32>,
+    ) -> SensorOffsets {
+        let offset = |raw: Option<SensorValue>, reference: Option<f32>, current: f32| match (raw, reference) {
+            (Some(raw), Some(reference)) => reference - raw.val,
+            _ => current,
+        };
+        SensorOffsets {
+            temperature: offset(self.raw_temperature(events, convention), reference_temperature, self.temperature_offset),
+            humidity: offset(self.raw_humidity(events, convention), reference_humidity, self.humidity_offset),
+        }
+    }
+}
+
+fn raw_value(reported: &SensorValue, offset: f32, convention: OffsetConvention) -> SensorValue {
+    match convention {
+        OffsetConvention::AlreadyApplied => reported.offset_by(-offset),
+        OffsetConvention::NotApplied => reported.clone(),
+    }
+}
+
+fn adjusted_value(reported: &SensorValue, offset: f32, convention: OffsetConvention) -> SensorValue {
+    match convention {
+        OffsetConvention::AlreadyApplied => reported.clone(),
+        OffsetConvention::NotApplied => reported.offset_by(offset),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-3, "{} is not near {}", actual, expected);
+    }
+
+    #[test]
+    fn test_offsets() {
+        let device: Device = Device {
+            temperature_offset: -0.5,
+            humidity_offset: 1.5,
+            ..Default::default()
+        };
+        let events = NewestEvents {
+            temperature: Some(SensorValue { val: 24.8, created_at: Default::default() }),
+            humidity: Some(SensorValue { val: 60.0, created_at: Default::default() }),
+            ..Default::default()
+        };
+        let applied = OffsetConvention::AlreadyApplied;
+        assert_near(device.raw_temperature(&events, applied).unwrap().val, 25.3);
+        assert_near(device.raw_humidity(&events, applied).unwrap().val, 58.5);
+        assert_near(device.adjusted_temperature(&events, applied).unwrap().val, 24.8);
+        assert_near(device.adjusted_humidity(&events, applied).unwrap().val, 60.0);
+
+        let not_applied = OffsetConvention::NotApplied;
+        assert_near(device.raw_temperature(&events, not_applied).unwrap().val, 24.8);
+        assert_near(device.raw_humidity(&events, not_applied).unwrap().val, 60.0);
+        assert_near(device.adjusted_temperature(&events, not_applied).unwrap().val, 24.3);
+        assert_near(device.adjusted_humidity(&events, not_applied).unwrap().val, 61.5);
+    }
+
+    #[test]
+    fn test_calibrate() {
+        let device: Device = Device {
+            temperature_offset: -0.5,
+            humidity_offset: 1.5,
+            ..Default::default()
+        };
+        let events = NewestEvents {
+            temperature: Some(SensorValue { val: 24.8, created_at: Default::default() }),
+            ..Default::default()
+        };
+        for (convention, raw) in [(OffsetConvention::AlreadyApplied, 25.3), (OffsetConvention::NotApplied, 24.8)] {
+            // The reference thermometer shows 24.0 degrees.
+            let offsets = device.calibrate(&events, convention, Some(24.0), Some(50.0));
+            assert_near(offsets.temperature, 24.0 - raw);
+            assert_near(offsets.humidity, 1.5);
+            let calibrated = Device {
+                temperature_offset: offsets.temperature,
+                humidity_offset: offsets.humidity,
+                ..device.clone()
+            };
+            // The next report of the same raw value under the convention.
+            let raw_value = SensorValue { val: raw, created_at: Default::default() };
+            let reported = NewestEvents {
+                temperature: Some(raw_value.offset_by(if convention == OffsetConvention::AlreadyApplied { offsets.temperature } else { 0.0 })),
+                ..Default::default()
+            };
+            assert_near(calibrated.adjusted_temperature(&reported, convention).unwrap().val, 24.0);
+            assert_eq!(calibrated.calibrate(&NewestEvents::default(), convention, Some(20.0), Some(50.0)), calibrated.offsets());
+        }
+    }
+}
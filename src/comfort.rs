@@ -0,0 +1,174 @@
+// Comfort metrics derived from temperature and humidity.
+// Copyright 2022-2023 Kenta Ida 
+// SPDX-License-Identifier: MIT
+//
+use libm::{expf, logf, sqrtf};
+
+use crate::device::NewestEvents;
+
+/// Heatstroke risk level of the guideline by Japanese Society of Biometeorology, classified by WBGT.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HeatstrokeRisk {
+    /// ほぼ安全: WBGT < 21
+    Safe,
+    /// 注意: 21 <= WBGT < 25
+    Caution,
+    /// 警戒: 25 <= WBGT < 28
+    Warning,
+    /// 厳重警戒: 28 <= WBGT < 31
+    SevereWarning,
+    /// 危険: 31 <= WBGT
+    Danger,
+}
+
+impl HeatstrokeRisk {
+    pub fn from_wbgt(wbgt: f32) -> Self {
+        if wbgt >= 31.0 {
+            Self::Danger
+        } else if wbgt >= 28.0 {
+            Self::SevereWarning
+        } else if wbgt >= 25.0 {
+            Self::Warning
+        } else if wbgt >= 21.0 {
+            Self::Caution
+        } else {
+            Self::Safe
+        }
+    }
+}
+
+/// Comfort metrics derived from a pair of temperature and relative humidity.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ComfortMetrics {
+    /// Temperature in degrees Celsius.
+    pub temperature: f32,
+    /// Relative humidity in percent.
+    pub humidity: f32,
+    /// Dew point in degrees Celsius.
+    pub dew_point: f32,
+    /// Absolute humidity in grams per cubic meter.
+    pub absolute_humidity: f32,
+    /// Heat index (apparent temperature) in degrees Celsius.
+    pub heat_index: f32,
+    /// Estimated indoor WBGT in degrees Celsius.
+    pub wbgt: f32,
+    pub heatstroke_risk: HeatstrokeRisk,
+}
+
+// Magnus formula coefficients over water.
+const MAGNUS_B: f32 = 17.62;
+const MAGNUS_C: f32 = 243.12;
+
+/// Saturation vapor pressure in hPa.
+fn saturation_vapor_pressure(temperature: f32) -> f32 {
+    6.112 * expf(MAGNUS_B * temperature / (MAGNUS_C + temperature))
+}
+
+fn dew_point(temperature: f32, humidity: f32) -> f32 {
+    let gamma = logf(humidity / 100.0) + MAGNUS_B * temperature / (MAGNUS_C + temperature);
+    MAGNUS_C * gamma / (MAGNUS_B - gamma)
+}
+
+fn absolute_humidity(temperature: f32, humidity: f32) -> f32 {
+    let vapor_pressure = saturation_vapor_pressure(temperature) * humidity / 100.0;
+    216.7 * vapor_pressure / (273.15 + temperature)
+}
+
+/// Heat index by the NWS (Rothfusz regression with adjustments).
+fn heat_index(temperature: f32, humidity: f32) -> f32 {
+    let t = temperature * 9.0 / 5.0 + 32.0;
+    let rh = humidity;
+    let simple = 0.5 * (t + 61.0 + (t - 68.0) * 1.2 + rh * 0.094);
+    let hi = if (simple + t) / 2.0 < 80.0 {
+        simple
+    } else {
+        let mut hi = -42.379 + 2.049_015_2 * t + 10.143_332 * rh
+            - 0.224_755_4 * t * rh
+            - 0.006_837_83 * t * t
+            - 0.054_817_17 * rh * rh
+            + 0.001_228_74 * t * t * rh
+            + 0.000_852_82 * t * rh * rh
+            - 0.000_001_99 * t * t * rh * rh;
+        if rh < 13.0 && (80.0..=112.0).contains(&t) {
+            hi -= (13.0 - rh) / 4.0 * sqrtf((17.0 - (t - 95.0).abs()) / 17.0);
+        } else if rh > 85.0 && (80.0..=87.0).contains(&t) {
+            hi += (rh - 85.0) / 10.0 * ((87.0 - t) / 5.0);
+        }
+        hi
+    };
+    (hi - 32.0) * 5.0 / 9.0
+}
+
+/// WBGT estimated from temperature and relative humidity without solar radiation and wind (Ono and Tonouchi, 2014).
+fn wbgt(temperature: f32, humidity: f32) -> f32 {
+    0.735 * temperature + 0.0374 * humidity + 0.00292 * temperature * humidity - 4.064
+}
+
+impl ComfortMetrics {
+    /// Compute the metrics from temperature in degrees Celsius and relative humidity in percent.
+    pub fn new(temperature: f32, humidity: f32) -> Self {
+        // Avoid ln(0) at the dew point.
+        let clamped = humidity.clamp(0.1, 100.0);
+        let wbgt = wbgt(temperature, clamped);
+        Self {
+            temperature,
+            humidity,
+            dew_point: dew_point(temperature, clamped),
+            absolute_humidity: absolute_humidity(temperature, clamped),
+            heat_index: heat_index(temperature, clamped),
+            wbgt,
+            heatstroke_risk: HeatstrokeRisk::from_wbgt(wbgt),
+        }
+    }
+}
+
+impl NewestEvents {
+    /// Compute comfort metrics from the temperature and humidity. Returns `None` if either of them is missing.
+    pub fn comfort_metrics(&self) -> Option<ComfortMetrics> {
+        Some(ComfortMetrics::new(self.temperature.as_ref()?.val, self.humidity.as_ref()?.val))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::device::SensorValue;
+
+    fn assert_near(actual: f32, expected: f32, tolerance: f32) {
+        assert!((actual - expected).abs() <= tolerance, "{} is not near {}", actual, expected);
+    }
+
+    #[test]
+    fn test_comfort_metrics() {
+        let metrics = ComfortMetrics::new(25.0, 50.0);
+        assert_near(metrics.dew_point, 13.9, 0.1);
+        assert_near(metrics.absolute_humidity, 11.5, 0.1);
+        assert_near(metrics.heat_index, 25.0, 0.5);
+        assert_near(metrics.wbgt, 19.8, 0.1);
+        assert_eq!(metrics.heatstroke_risk, HeatstrokeRisk::Safe);
+
+        let metrics = ComfortMetrics::new(32.0, 70.0);
+        assert_near(metrics.dew_point, 25.9, 0.1);
+        assert_near(metrics.heat_index, 40.9, 0.5);
+        assert_near(metrics.wbgt, 28.6, 0.1);
+        assert_eq!(metrics.heatstroke_risk, HeatstrokeRisk::SevereWarning);
+
+        // The humidity is kept as given, though it is clamped in the formulas.
+        let metrics = ComfortMetrics::new(25.0, 0.0);
+        assert_eq!(metrics.humidity, 0.0);
+        assert!(metrics.dew_point.is_finite());
+    }
+
+    #[test]
+    fn test_comfort_metrics_from_newest_events() {
+        let mut events = NewestEvents {
+            temperature: Some(SensorValue { val: 24.8, created_at: Default::default() }),
+            ..Default::default()
+        };
+        assert!(events.comfort_metrics().is_none());
+        events.humidity = Some(SensorValue { val: 70.0, created_at: Default::default() });
+        let metrics = events.comfort_metrics().unwrap();
+        assert_eq!(metrics.temperature, 24.8);
+        assert_eq!(metrics.heatstroke_risk, HeatstrokeRisk::Caution);
+    }
+}
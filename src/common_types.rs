@@ -3,7 +3,10 @@
 // SPDX-License-Identifier: MIT
 //
 
-use chrono::{DateTime, Utc};
+use core::fmt;
+
+use chrono::{format::ParseErrorKind, DateTime, Utc};
+use fuga_json_seq_parser::JsonNode;
 use heapless::String;
 
 use crate::config::SERIAL_NUMBER_LEN;
@@ -12,29 +15,241 @@ pub type Timestamp = DateTime<Utc>;
 pub type SerialNumber = String<SERIAL_NUMBER_LEN>;
 
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ModelNodeParseError {
-    UuidParseError,
-    TimestampParseError,
+    UuidParseError(UuidErrorCause),
+    TimestampParseError(TimestampErrorCause),
     MacAddressParseError,
+    FirmwareVersionParseError,
     UnexpectedEnumValue,
-    UnknownNewestEventsType,
+    InvalidPropertyValue,
+    /// A numeric field is neither a number nor a numeric string, e.g. `null`.
+    InvalidNumber,
+    /// A number does not fit in its field, e.g. a negative or fractional `epc`.
+    NumberOutOfRange,
     NodeTooDeep,
     StringTooLong,
     UnexpectedMapArrayEnd,
     UnexpectedParserState,
-    UnexpectedNode(UnexpectedNodeError),
+    UnexpectedNode(NodeKind),
+    /// The document violates the schema in strict mode. The violations are reported to the sink of `ParserOptions::strict`.
+    SchemaViolation,
+}
+
+/// Cause of a UUID parse error, found from the input as `uuid::Error` does not expose it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UuidErrorCause {
+    InvalidCharacter,
+    InvalidLength,
+    InvalidGroupCount,
+    InvalidGroupLength,
+    Other,
+}
+
+/// Cause of a timestamp parse error, from `chrono::format::ParseErrorKind`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TimestampErrorCause {
+    OutOfRange,
+    Impossible,
+    NotEnough,
+    Invalid,
+    TooShort,
+    TooLong,
+    BadFormat,
+    Other,
 }
 
-pub type UnexpectedNodeError = String<64>;
+/// Kind of a JSON node, to report an unexpected node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum NodeKind {
+    StartMap,
+    EndMap,
+    StartArray,
+    EndArray,
+    Key,
+    Value,
+}
 
+impl From<&JsonNode<'_>> for NodeKind {
+    fn from(node: &JsonNode) -> Self {
+        match node {
+            JsonNode::StartMap => Self::StartMap,
+            JsonNode::EndMap => Self::EndMap,
+            JsonNode::StartArray => Self::StartArray,
+            JsonNode::EndArray => Self::EndArray,
+            JsonNode::Key(_) => Self::Key,
+            JsonNode::Value(_) => Self::Value,
+        }
+    }
+}
+
+/// `uuid::Error` does not expose its kind, so the cause is `Other`. The parsers use `parse_uuid` to find the cause.
 impl From<uuid::Error> for ModelNodeParseError {
     fn from(_: uuid::Error) -> Self {
-        Self::UuidParseError
+        Self::UuidParseError(UuidErrorCause::Other)
+    }
+}
+
+/// Parse a UUID in the formats accepted by `uuid::Uuid::try_parse`, finding the cause of a failure from the input.
+pub(crate) fn parse_uuid(s: &str) -> Result<uuid::Uuid, ModelNodeParseError> {
+    uuid::Uuid::try_parse(s).map_err(|_| ModelNodeParseError::UuidParseError(uuid_error_cause(s)))
+}
+
+fn uuid_error_cause(s: &str) -> UuidErrorCause {
+    const GROUP_LENGTHS: [usize; 5] = [8, 4, 4, 4, 12];
+    let s = s
+        .strip_prefix("urn:uuid:")
+        .or_else(|| s.strip_prefix('{').and_then(|s| s.strip_suffix('}')))
+        .unwrap_or(s);
+    let is_valid_char = |c: char| c == '-' || c.is_ascii_hexdigit();
+    if !s.contains('-') {
+        // Simple format without hyphens.
+        return if s.len() != 32 {
+            UuidErrorCause::InvalidLength
+        } else if !s.chars().all(is_valid_char) {
+            UuidErrorCause::InvalidCharacter
+        } else {
+            UuidErrorCause::Other
+        };
+    }
+    if s.split('-').count() != GROUP_LENGTHS.len() {
+        UuidErrorCause::InvalidGroupCount
+    } else if s.split('-').zip(GROUP_LENGTHS).any(|(group, length)| group.len() != length) {
+        UuidErrorCause::InvalidGroupLength
+    } else if !s.chars().all(is_valid_char) {
+        UuidErrorCause::InvalidCharacter
+    } else {
+        UuidErrorCause::Other
     }
 }
+
 impl From<chrono::ParseError> for ModelNodeParseError {
-    fn from(_: chrono::ParseError) -> Self {
-        Self::TimestampParseError
+    fn from(error: chrono::ParseError) -> Self {
+        let cause = match error.kind() {
+            ParseErrorKind::OutOfRange => TimestampErrorCause::OutOfRange,
+            ParseErrorKind::Impossible => TimestampErrorCause::Impossible,
+            ParseErrorKind::NotEnough => TimestampErrorCause::NotEnough,
+            ParseErrorKind::Invalid => TimestampErrorCause::Invalid,
+            ParseErrorKind::TooShort => TimestampErrorCause::TooShort,
+            ParseErrorKind::TooLong => TimestampErrorCause::TooLong,
+            ParseErrorKind::BadFormat => TimestampErrorCause::BadFormat,
+            _ => TimestampErrorCause::Other,
+        };
+        Self::TimestampParseError(cause)
     }
-}
\ No newline at end of file
+}
+
+impl fmt::Display for UuidErrorCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::InvalidCharacter => "invalid character",
+            Self::InvalidLength => "invalid length",
+            Self::InvalidGroupCount => "invalid group count",
+            Self::InvalidGroupLength => "invalid group length",
+            Self::Other => "malformed",
+        })
+    }
+}
+
+impl fmt::Display for TimestampErrorCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::OutOfRange => "field out of range",
+            Self::Impossible => "impossible date or time",
+            Self::NotEnough => "not enough fields",
+            Self::Invalid => "invalid character",
+            Self::TooShort => "premature end of input",
+            Self::TooLong => "trailing input",
+            Self::BadFormat => "bad format",
+            Self::Other => "malformed",
+        })
+    }
+}
+
+impl fmt::Display for NodeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::StartMap => "start of map",
+            Self::EndMap => "end of map",
+            Self::StartArray => "start of array",
+            Self::EndArray => "end of array",
+            Self::Key => "key",
+            Self::Value => "value",
+        })
+    }
+}
+
+impl fmt::Display for ModelNodeParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UuidParseError(cause) => write!(f, "failed to parse UUID: {}", cause),
+            Self::TimestampParseError(cause) => write!(f, "failed to parse timestamp: {}", cause),
+            Self::MacAddressParseError => f.write_str("failed to parse MAC address"),
+            Self::FirmwareVersionParseError => f.write_str("failed to parse firmware version"),
+            Self::UnexpectedEnumValue => f.write_str("unexpected enum value"),
+            Self::InvalidPropertyValue => f.write_str("invalid property value"),
+            Self::InvalidNumber => f.write_str("invalid number"),
+            Self::NumberOutOfRange => f.write_str("number out of range"),
+            Self::NodeTooDeep => f.write_str("node too deep"),
+            Self::StringTooLong => f.write_str("string too long"),
+            Self::UnexpectedMapArrayEnd => f.write_str("unexpected end of map or array"),
+            Self::UnexpectedParserState => f.write_str("unexpected parser state"),
+            Self::UnexpectedNode(node) => write!(f, "unexpected {}", node),
+            Self::SchemaViolation => f.write_str("schema violation"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ModelNodeParseError {}
+
+#[cfg(test)]
+mod test {
+    use core::fmt::Write;
+    use core::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_uuid_error_causes() {
+        let uuid = uuid::Uuid::from_u128(0xf262cb0c_a853_47bb_9559_44d0f2c4d6e1);
+        assert_eq!(parse_uuid("f262cb0c-a853-47bb-9559-44d0f2c4d6e1"), Ok(uuid));
+        assert_eq!(parse_uuid("f262cb0ca85347bb955944d0f2c4d6e1"), Ok(uuid));
+        assert_eq!(parse_uuid("{f262cb0c-a853-47bb-9559-44d0f2c4d6e1}"), Ok(uuid));
+        assert_eq!(parse_uuid("urn:uuid:f262cb0c-a853-47bb-9559-44d0f2c4d6e1"), Ok(uuid));
+
+        let cause = |s| match parse_uuid(s) {
+            Err(ModelNodeParseError::UuidParseError(cause)) => cause,
+            result => panic!("unexpected result - {:?}", result),
+        };
+        assert_eq!(cause(""), UuidErrorCause::InvalidLength);
+        assert_eq!(cause("f262cb0ca85347bb955944d0f2c4d6e"), UuidErrorCause::InvalidLength);
+        assert_eq!(cause("f262cb0ca85347bb955944d0f2c4d6eX"), UuidErrorCause::InvalidCharacter);
+        assert_eq!(cause("{f262cb0ca85347bb955944d0f2c4d6eX}"), UuidErrorCause::InvalidCharacter);
+        assert_eq!(cause("f262cb0c-a853-47bb-955944d0f2c4d6e1"), UuidErrorCause::InvalidGroupCount);
+        assert_eq!(cause("f262cb0c-a853-47bb-9559-44d0-f2c4d6e1"), UuidErrorCause::InvalidGroupCount);
+        assert_eq!(cause("f262cb0ca-853-47bb-9559-44d0f2c4d6e1"), UuidErrorCause::InvalidGroupLength);
+        assert_eq!(cause("urn:uuid:f262cb0c-a853-47bb-9559-44d0f2c4d6e"), UuidErrorCause::InvalidGroupLength);
+        assert_eq!(cause("f262cb0c-a853-47bb-9559-44d0f2c4d6eX"), UuidErrorCause::InvalidCharacter);
+        assert_eq!(cause("f262cb0c-a853-47bb-9559-44d0f2c4d6\u{e9}"), UuidErrorCause::InvalidCharacter);
+    }
+
+    #[test]
+    fn test_error_causes() {
+        assert_eq!(parse_uuid("0123456789"), Err(ModelNodeParseError::UuidParseError(UuidErrorCause::InvalidLength)));
+        assert_eq!(
+            parse_uuid("f262cb0c-a853-47bb-9559-44d0f2c4d6eX"),
+            Err(ModelNodeParseError::UuidParseError(UuidErrorCause::InvalidCharacter))
+        );
+        let error = ModelNodeParseError::from(Timestamp::from_str("2022-13-18T06:42:59Z").unwrap_err());
+        assert_eq!(error, ModelNodeParseError::TimestampParseError(TimestampErrorCause::OutOfRange));
+
+        let mut s = String::<64>::new();
+        write!(&mut s, "{}", error).unwrap();
+        assert_eq!(s.as_str(), "failed to parse timestamp: field out of range");
+    }
+}
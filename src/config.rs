@@ -2,6 +2,17 @@
 // Copyright 2022-2023 Kenta Ida 
 // SPDX-License-Identifier: MIT
 //
+use core::{fmt::Debug, ops::Deref};
+
+use embedded_io::blocking::Read;
+use fuga_json_seq_parser::{JsonNode, ParserCallbackAction};
+use fuga_json_seq_parser::Parser as JsonParser;
+use fuga_json_seq_parser::ParserError as JsonParserError;
+use heapless::String;
+
+use crate::common_types::ModelNodeParseError;
+use crate::parser_options::{ParserOptions, append_string_option, copy_string_option};
+
 pub const MAX_FIRMWARE_VERSION_LEN: usize = 48;
 pub const MAX_NICKNAME_LEN: usize = 48;
 pub const MAX_MODEL_NAME_LEN: usize = 64;
@@ -16,6 +27,8 @@ pub const MAX_ECHONET_LITE_VALUE_LEN: usize = 16;
 pub const ID_LEN: usize = 36;
 pub const TIMESTAMP_LEN: usize = 20;
 pub const SERIAL_NUMBER_LEN: usize = 14;
+/// Maximum depth of the JSON path kept in `ErrorLocation`.
+pub const MAX_ERROR_PATH_DEPTH: usize = 10;
 
 const fn max_usize_array(a: &[usize]) -> usize {
     let mut max = 0;
@@ -39,4 +52,131 @@ pub const REQUIRED_DEVICES_PARSER_BUFFER_LEN: usize = max_usize_array(&[
     TIMESTAMP_LEN,
 ]) + 2;
 
-pub const REQUIRED_APPLIANCES_PARSER_BUFFER_LEN: usize = REQUIRED_DEVICES_PARSER_BUFFER_LEN;
\ No newline at end of file
+pub const REQUIRED_APPLIANCES_PARSER_BUFFER_LEN: usize = REQUIRED_DEVICES_PARSER_BUFFER_LEN;
+
+/// String storage of a model field, e.g. `heapless::String<N>`.
+pub trait ModelString: Clone + Debug + Default + PartialEq + Deref<Target = str> {
+    /// Copy `s` into a new storage. Too long strings are handled as specified by `options`.
+    fn copy_from(s: &str, options: &ParserOptions) -> Result<Self, ModelNodeParseError>;
+    /// Append `s` to the storage, for the pieces of a string longer than the parser buffer.
+    /// Too long strings are handled as specified by `options`.
+    fn append(&mut self, s: &str, options: &ParserOptions) -> Result<(), ModelNodeParseError>;
+}
+
+impl<const N: usize> ModelString for String<N> {
+    fn copy_from(s: &str, options: &ParserOptions) -> Result<Self, ModelNodeParseError> {
+        copy_string_option(s, options)
+    }
+    fn append(&mut self, s: &str, options: &ParserOptions) -> Result<(), ModelNodeParseError> {
+        append_string_option(self, s, options)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl ModelString for alloc::string::String {
+    fn copy_from(s: &str, _options: &ParserOptions) -> Result<Self, ModelNodeParseError> {
+        Ok(s.into())
+    }
+    fn append(&mut self, s: &str, _options: &ParserOptions) -> Result<(), ModelNodeParseError> {
+        self.push_str(s);
+        Ok(())
+    }
+}
+
+/// JSON parser used by the `read_*` functions, i.e. `fuga_json_seq_parser::Parser<BUFFER_SIZE, MAX_DEPTH>`.
+pub trait JsonDocumentParser {
+    fn new() -> Self;
+    fn set_bytes_remaining(&mut self, bytes_remaining: Option<usize>);
+    fn parse<I: Read, F, CallbackError>(
+        &mut self,
+        reader: &mut I,
+        callback: F,
+    ) -> Result<bool, JsonParserError<I::Error, CallbackError>>
+    where
+        F: for<'node> FnMut(JsonNode<'node>) -> Result<ParserCallbackAction, CallbackError>;
+}
+
+impl<const BUFFER_SIZE: usize, const MAX_DEPTH: usize> JsonDocumentParser for JsonParser<BUFFER_SIZE, MAX_DEPTH> {
+    fn new() -> Self {
+        JsonParser::new()
+    }
+    fn set_bytes_remaining(&mut self, bytes_remaining: Option<usize>) {
+        JsonParser::set_bytes_remaining(self, bytes_remaining)
+    }
+    fn parse<I: Read, F, CallbackError>(
+        &mut self,
+        reader: &mut I,
+        callback: F,
+    ) -> Result<bool, JsonParserError<I::Error, CallbackError>>
+    where
+        F: for<'node> FnMut(JsonNode<'node>) -> Result<ParserCallbackAction, CallbackError>,
+    {
+        JsonParser::parse(self, reader, callback)
+    }
+}
+
+/// Capacities of the strings in the models, and the parsers whose buffers can hold them.
+///
+/// `DefaultCapacities` uses the constants above. Implement this trait on a marker type to use another profile,
+/// e.g. smaller strings on a RAM-constrained node. The buffer of the parsers must be longer than the longest string
+/// value to hold, including the quotes.
+pub trait Capacities: Clone + Debug + Default + PartialEq {
+    type Nickname: ModelString;
+    type DeviceName: ModelString;
+    type FirmwareVersion: ModelString;
+    type ModelName: ModelString;
+    type Manufacturer: ModelString;
+    type RemoteName: ModelString;
+    type Series: ModelString;
+    type Image: ModelString;
+    type Country: ModelString;
+    type EchonetLiteName: ModelString;
+    type EchonetLiteValue: ModelString;
+    /// Parser of `read_devices_with_capacities`.
+    type DevicesParser: JsonDocumentParser;
+    /// Parser of `read_appliances_with_capacities`.
+    type AppliancesParser: JsonDocumentParser;
+}
+
+/// Capacities defined by the constants in this module.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DefaultCapacities;
+
+impl Capacities for DefaultCapacities {
+    type Nickname = String<MAX_NICKNAME_LEN>;
+    type DeviceName = String<MAX_DEVICE_NAME_LEN>;
+    type FirmwareVersion = String<MAX_FIRMWARE_VERSION_LEN>;
+    type ModelName = String<MAX_MODEL_NAME_LEN>;
+    type Manufacturer = String<MAX_MANUFACTURER_LEN>;
+    type RemoteName = String<MAX_REMOTE_NAME_LEN>;
+    type Series = String<MAX_SERIES_LEN>;
+    type Image = String<MAX_IMAGE_LEN>;
+    type Country = String<MAX_COUNTRY_LEN>;
+    type EchonetLiteName = String<MAX_ECHONET_LITE_NAME_LEN>;
+    type EchonetLiteValue = String<MAX_ECHONET_LITE_VALUE_LEN>;
+    type DevicesParser = JsonParser<REQUIRED_DEVICES_PARSER_BUFFER_LEN, 5>;
+    type AppliancesParser = JsonParser<REQUIRED_APPLIANCES_PARSER_BUFFER_LEN, 10>;
+}
+
+/// Capacities of the models used by `owned`, whose strings are not limited and never truncated.
+/// Strings longer than the parser buffer are concatenated from their pieces.
+#[cfg(feature = "alloc")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AllocCapacities;
+
+#[cfg(feature = "alloc")]
+impl Capacities for AllocCapacities {
+    type Nickname = alloc::string::String;
+    type DeviceName = alloc::string::String;
+    type FirmwareVersion = alloc::string::String;
+    type ModelName = alloc::string::String;
+    type Manufacturer = alloc::string::String;
+    type RemoteName = alloc::string::String;
+    type Series = alloc::string::String;
+    type Image = alloc::string::String;
+    type Country = alloc::string::String;
+    type EchonetLiteName = alloc::string::String;
+    type EchonetLiteValue = alloc::string::String;
+    type DevicesParser = JsonParser<REQUIRED_DEVICES_PARSER_BUFFER_LEN, 5>;
+    type AppliancesParser = JsonParser<REQUIRED_APPLIANCES_PARSER_BUFFER_LEN, 10>;
+}
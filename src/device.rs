@@ -2,79 +2,93 @@
 // Copyright 2022-2023 Kenta Ida 
 // SPDX-License-Identifier: MIT
 //
-use core::{fmt::Write, str::FromStr};
+use core::{fmt::{self, Write}, str::FromStr};
 
-use heapless::String;
 use fuga_json_seq_parser::{JsonScalarValue, ParserCallbackAction, JsonNode};
-use fuga_json_seq_parser::Parser as JsonParser;
 use fuga_json_seq_parser::ParserError as JsonParserError;
 
 use nom::{
-    character::complete::one_of,
-    combinator::{map, recognize},
+    branch::alt,
+    character::complete::{char, one_of},
+    combinator::{all_consuming, map, recognize},
     error::{ContextError, ParseError},
-    sequence::{pair, separated_pair},
+    sequence::{pair, separated_pair, tuple},
     IResult,
 };
 use uuid::Uuid;
 use crate::config::*;
 use crate::common_types::*;
 use crate::node_key::*;
-use crate::parser_options::{ParserOptions, copy_string_option};
+use crate::location::{CountingReader, PathTracker, ReadError};
+use crate::parser_options::{ParserOptions, is_string};
+use crate::statistics::ReadStatistics;
+use crate::numeric::coerce_number;
+use crate::schema::{
+    ViolationKind, DEVICE_KEYS, NEWEST_EVENTS_KEYS, REQUIRED_DEVICE_FIELDS, REQUIRED_SENSOR_VALUE_FIELDS, REQUIRED_USER_FIELDS,
+    SENSOR_VALUE_KEYS, USER_KEYS,
+};
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SensorValue {
     pub val: f32,
     pub created_at: Timestamp,
 }
 #[derive(Debug, Default)]
-pub struct User {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct User<C: Capacities = DefaultCapacities> {
     pub id: Uuid,
-    pub nickname: String<MAX_NICKNAME_LEN>,
+    pub nickname: C::Nickname,
     pub superuser: bool,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NewestEvents {
+    #[cfg_attr(feature = "serde", serde(rename = "te", default, skip_serializing_if = "Option::is_none"))]
     pub temperature: Option<SensorValue>,
+    #[cfg_attr(feature = "serde", serde(rename = "hu", default, skip_serializing_if = "Option::is_none"))]
     pub humidity: Option<SensorValue>,
+    #[cfg_attr(feature = "serde", serde(rename = "il", default, skip_serializing_if = "Option::is_none"))]
     pub illumination: Option<SensorValue>,
+    #[cfg_attr(feature = "serde", serde(rename = "mo", default, skip_serializing_if = "Option::is_none"))]
     pub motion: Option<SensorValue>,
 }
 
 #[derive(Clone, Debug, Default, PartialEq)]
-pub struct Device {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Device<C: Capacities = DefaultCapacities> {
     pub id: Uuid,
-    pub name: String<MAX_DEVICE_NAME_LEN>,
+    pub name: C::DeviceName,
     pub temperature_offset: f32,
     pub humidity_offset: f32,
     pub created_at: Timestamp,
     pub updated_at: Timestamp,
-    pub firmware_version: String<MAX_FIRMWARE_VERSION_LEN>,
+    pub firmware_version: C::FirmwareVersion,
     pub mac_address: MacAddress,
     pub bt_mac_address: MacAddress,
     pub serial_number: SerialNumber,
 }
 
 #[derive(Debug, Default)]
-pub struct Model {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Model<C: Capacities = DefaultCapacities> {
     pub id: Uuid,
-    pub country: String<MAX_COUNTRY_LEN>,
-    pub manifacturer: String<MAX_MANUFACTURER_LEN>,
-    pub remote_name: String<MAX_REMOTE_NAME_LEN>,
-    pub series: String<MAX_SERIES_LEN>,
-    pub name: String<MAX_MODEL_NAME_LEN>,
-    pub image: String<MAX_IMAGE_LEN>,
+    pub country: C::Country,
+    #[cfg_attr(feature = "serde", serde(rename = "manufacturer"))]
+    pub manifacturer: C::Manufacturer,
+    pub remote_name: C::RemoteName,
+    pub series: C::Series,
+    pub name: C::ModelName,
+    pub image: C::Image,
 }
 
 #[derive(Debug)]
-pub enum DeviceSubNode {
-    User(User),
+pub enum DeviceSubNode<C: Capacities = DefaultCapacities> {
+    User(User<C>),
     NewestEvents(NewestEvents),
 }
 
-type DevicesParser = JsonParser<REQUIRED_DEVICES_PARSER_BUFFER_LEN, 5>;
-
 #[derive(Clone, Copy, Debug)]
 enum DevicesParserState {
     Start,
@@ -87,6 +101,19 @@ enum DevicesParserState {
     UnknownMapArray,
 }
 
+impl DevicesParserState {
+    /// Keys known in the map of this state.
+    fn keys(&self) -> FieldMask {
+        match self {
+            Self::DeviceMap => DEVICE_KEYS.with(ModelNodeKey::Users).with(ModelNodeKey::NewestEvents),
+            Self::UserMap => USER_KEYS,
+            Self::NewestEventsMap => NEWEST_EVENTS_KEYS,
+            Self::NewestEventMap(_) => SENSOR_VALUE_KEYS,
+            _ => FieldMask::ALL,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 enum NewestEventType {
     Temperature,
@@ -95,9 +122,21 @@ enum NewestEventType {
     Motion,
 }
 
+impl NewestEventType {
+    fn key(&self) -> ModelNodeKey {
+        match self {
+            Self::Temperature => ModelNodeKey::Te,
+            Self::Humidity => ModelNodeKey::Hu,
+            Self::Illumination => ModelNodeKey::Il,
+            Self::Motion => ModelNodeKey::Mo,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct MacAddress(pub [u8; 6]);
 
+#[allow(clippy::derivable_impls)]
 impl Default for MacAddress {
     fn default() -> Self {
         Self([0u8; 6])
@@ -120,7 +159,7 @@ fn parse_mac_address<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     let delimiter_list = ":-";
 
     map(
-        separated_pair(
+        all_consuming(separated_pair(
             parse_byte_string,
             one_of(delimiter_list),
             separated_pair(
@@ -140,39 +179,217 @@ fn parse_mac_address<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
                     ),
                 ),
             ),
-        ),
+        )),
         |(l, r)| [l, r.0, r.1 .0, r.1 .1 .0, r.1 .1 .1 .0, r.1 .1 .1 .1],
     )(i)
 }
 
+fn parse_mac_address_dotted<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, [u8; 6], E> {
+    map(
+        all_consuming(tuple((
+            parse_byte_string,
+            parse_byte_string,
+            char('.'),
+            parse_byte_string,
+            parse_byte_string,
+            char('.'),
+            parse_byte_string,
+            parse_byte_string,
+        ))),
+        |(b0, b1, _, b2, b3, _, b4, b5)| [b0, b1, b2, b3, b4, b5],
+    )(i)
+}
+
+fn parse_mac_address_bare<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, [u8; 6], E> {
+    map(
+        all_consuming(tuple((
+            parse_byte_string,
+            parse_byte_string,
+            parse_byte_string,
+            parse_byte_string,
+            parse_byte_string,
+            parse_byte_string,
+        ))),
+        |(b0, b1, b2, b3, b4, b5)| [b0, b1, b2, b3, b4, b5],
+    )(i)
+}
+
+/// Parse a MAC address in the form of `aa:bb:cc:dd:ee:ff`, `aa-bb-cc-dd-ee-ff`, `aabb.ccdd.eeff` or `aabbccddeeff`.
 impl FromStr for MacAddress {
     type Err = ModelNodeParseError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (_, mac_address) = parse_mac_address(s)
+        let (_, mac_address) = alt((parse_mac_address, parse_mac_address_dotted, parse_mac_address_bare))(s)
             .map_err(|_: nom::Err<()>| ModelNodeParseError::MacAddressParseError)?;
         Ok(Self(mac_address))
     }
 }
 
+/// Formatting style of `MacAddress`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MacAddressStyle {
+    /// `aa:bb:cc:dd:ee:ff`
+    #[default]
+    Colon,
+    /// `aa-bb-cc-dd-ee-ff`
+    Hyphen,
+    /// `aabb.ccdd.eeff`
+    Dotted,
+    /// `aabbccddeeff`
+    Bare,
+}
+
+/// Helper to format `MacAddress` in a style. Created by `MacAddress::display`.
+/// Hex digits are in upper case with the alternate flag `{:#}`.
+pub struct MacAddressDisplay<'a> {
+    address: &'a MacAddress,
+    style: MacAddressStyle,
+}
+
+impl fmt::Display for MacAddressDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, byte) in self.address.0.iter().enumerate() {
+            let separator = match (self.style, index) {
+                (_, 0) => None,
+                (MacAddressStyle::Colon, _) => Some(':'),
+                (MacAddressStyle::Hyphen, _) => Some('-'),
+                (MacAddressStyle::Dotted, 2 | 4) => Some('.'),
+                (MacAddressStyle::Dotted, _) | (MacAddressStyle::Bare, _) => None,
+            };
+            if let Some(separator) = separator {
+                f.write_char(separator)?;
+            }
+            if f.alternate() {
+                write!(f, "{:02X}", byte)?;
+            } else {
+                write!(f, "{:02x}", byte)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Known vendors by OUI.
+/// Nature is not listed, as the addresses of Remo devices seen so far come from the OUIs of their Espressif modules
+/// and no OUI registered to Nature has been confirmed on a device. Add it here once confirmed.
+const OUI_VENDORS: &[([u8; 3], &str)] = &[
+    ([0x24, 0x6f, 0x28], "Espressif"),
+    ([0x34, 0xab, 0x95], "Espressif"),
+    ([0xc8, 0x2b, 0x96], "Espressif"),
+    ([0xe8, 0xdb, 0x84], "Espressif"),
+    ([0xf0, 0x08, 0xd1], "Espressif"),
+];
+
+impl MacAddress {
+    /// Format in the style.
+    pub fn display(&self, style: MacAddressStyle) -> MacAddressDisplay<'_> {
+        MacAddressDisplay { address: self, style }
+    }
+
+    /// Organizationally unique identifier, the first three octets.
+    pub fn oui(&self) -> [u8; 3] {
+        [self.0[0], self.0[1], self.0[2]]
+    }
+
+    /// Vendor name of the OUI, if it is known. Remo devices are built on Espressif modules.
+    pub fn vendor(&self) -> Option<&'static str> {
+        let oui = self.oui();
+        OUI_VENDORS.iter().find(|(prefix, _)| *prefix == oui).map(|(_, vendor)| *vendor)
+    }
+}
+
+/// Format in the colon style, e.g. `aa:bb:cc:dd:ee:ff`.
+impl fmt::Display for MacAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.display(MacAddressStyle::Colon), f)
+    }
+}
+
+/// Format as bare hex digits, e.g. `aabbccddeeff`, prefixed with `0x` by the `#` flag as integers.
+impl fmt::LowerHex for MacAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            f.write_str("0x")?;
+        }
+        self.0.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+/// Format as bare hex digits, e.g. `AABBCCDDEEFF`, prefixed with `0x` by the `#` flag as integers.
+impl fmt::UpperHex for MacAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            f.write_str("0x")?;
+        }
+        self.0.iter().try_for_each(|byte| write!(f, "{:02X}", byte))
+    }
+}
+
+// The error carries its location in bounded storage, as it cannot be boxed without alloc.
+#[allow(clippy::result_large_err)]
 pub fn read_devices<R: embedded_io::blocking::Read, F>(
     reader: &mut R,
     total_length: Option<usize>,
     options: &ParserOptions,
-    mut callback: F,
-) -> Result<(), JsonParserError<R::Error, ModelNodeParseError>>
+    callback: F,
+) -> Result<ReadStatistics, ReadError<R::Error>>
 where
     F: for<'a> FnMut(&'a Device, Option<&'a DeviceSubNode>),
 {
-    let mut parser = DevicesParser::new();
+    read_devices_with_capacities::<DefaultCapacities, _, _>(reader, total_length, options, callback)
+}
+
+/// `read_devices` with the string capacities and the parser of `C`.
+#[allow(clippy::result_large_err)]
+pub fn read_devices_with_capacities<C: Capacities, R: embedded_io::blocking::Read, F>(
+    reader: &mut R,
+    total_length: Option<usize>,
+    options: &ParserOptions,
+    callback: F,
+) -> Result<ReadStatistics, ReadError<R::Error>>
+where
+    F: for<'a> FnMut(&'a Device<C>, Option<&'a DeviceSubNode<C>>),
+{
+    read_devices_with_ends::<C, _, _, _>(reader, total_length, options, callback, |_| {})
+}
+
+/// `read_devices_with_capacities` which also passes each device to `device_end` at the end of its map,
+/// including the devices without `users`.
+#[allow(clippy::result_large_err)]
+pub(crate) fn read_devices_with_ends<C: Capacities, R: embedded_io::blocking::Read, F, E>(
+    reader: &mut R,
+    total_length: Option<usize>,
+    options: &ParserOptions,
+    mut callback: F,
+    mut device_end: E,
+) -> Result<ReadStatistics, ReadError<R::Error>>
+where
+    F: for<'a> FnMut(&'a Device<C>, Option<&'a DeviceSubNode<C>>),
+    E: FnMut(&Device<C>),
+{
+    let mut parser = C::DevicesParser::new();
     parser.set_bytes_remaining(total_length);
-    let mut device = Device::default();
+    let mut reader = CountingReader::new(reader);
+    let mut path = PathTracker::new();
+    let mut device = Device::<C>::default();
     let mut subnode = DeviceSubNode::User(User::default());
     let mut state = DevicesParserState::Start;
     let mut node_key = None;
     let mut unknown_map_depth = 0;
     let mut unknown_array_depth = 0;
+    let mut unknown_parent = DevicesParserState::DeviceMap;  // State to return to after skipping unknown maps and arrays.
+    let mut index = 0;  // Index of the current device.
+    let mut statistics = ReadStatistics::default();
+    // Keys seen in the current device map and sub node map, and the number of violations in strict mode.
+    let mut seen = FieldMask::NONE;
+    let mut sub_seen = FieldMask::NONE;
+    let mut violations = 0;
 
-    while !parser.parse(reader, |node| {
+    while !parser.parse(&mut reader, |node| {
+        path.update(&node);
         let new_state = match (state, node) {
             (DevicesParserState::Start, JsonNode::StartArray) => {
                 DevicesParserState::DevicesArray
@@ -181,15 +398,35 @@ where
                 DevicesParserState::Start
             }
             (DevicesParserState::DevicesArray, JsonNode::StartMap) => {
+                seen = FieldMask::NONE;
+                device = Device::default();
                 DevicesParserState::DeviceMap
             }
             (DevicesParserState::DeviceMap, JsonNode::EndMap) => {
+                options.check_required(REQUIRED_DEVICE_FIELDS, seen, path.path(), &mut violations);
+                device_end(&device);
+                // Counted here as devices without `users` are not passed to the callback.
+                statistics.elements += 1;
+                index += 1;
                 DevicesParserState::DevicesArray
             }
             (map_state, JsonNode::Key(key)) => {
+                #[allow(clippy::single_match)]
                 match key {
                     JsonScalarValue::String(key) => {
-                        node_key = ModelNodeKey::try_from(key).ok(); // Store key
+                        node_key = ModelNodeKey::try_from(key).ok().filter(|key| map_state.keys().contains(*key)); // Store key
+                        match (node_key, map_state) {
+                            (Some(key), DevicesParserState::DeviceMap) => seen = seen.with(key),
+                            (Some(key), DevicesParserState::UserMap | DevicesParserState::NewestEventMap(_)) => sub_seen = sub_seen.with(key),
+                            (None, DevicesParserState::UnknownMapArray) => {}
+                            (None, state) => {
+                                path.mark_unknown_key();
+                                statistics.unknown_keys += 1;
+                                let unmodeled = matches!(state, DevicesParserState::DeviceMap);
+                                options.report_unknown_key(&path, key, unmodeled, &mut violations);
+                            }
+                            _ => {}
+                        }
                     }
                     _ => {}
                 }
@@ -197,39 +434,47 @@ where
             }
             // Process map node for device.
             (DevicesParserState::DeviceMap, JsonNode::Value(value)) => {
-                if let Some(node_key) = node_key.take() {
+                if let Some(node_key) = options.take_key(&mut node_key, &value) {
                     match (node_key, value) {
-                        (ModelNodeKey::Name, JsonScalarValue::String(s)) => {
-                            device.name = copy_string_option(s, options)?;
+                        (ModelNodeKey::Name, value) if is_string(&value) => {
+                            options.copy_piece(&mut device.name, node_key, index, value, &mut statistics)?;
                         }
                         (ModelNodeKey::Id, JsonScalarValue::String(s)) => {
-                            device.id = Uuid::from_str(s)?
+                            device.id = options.recover_field(node_key, index, parse_uuid(s))?
                         }
                         (ModelNodeKey::CreatedAt, JsonScalarValue::String(s)) => {
-                            device.created_at = Timestamp::from_str(s)?
+                            device.created_at = options.recover_field(node_key, index, Timestamp::from_str(s))?
                         }
                         (ModelNodeKey::UpdatedAt, JsonScalarValue::String(s)) => {
-                            device.updated_at = Timestamp::from_str(s)?
+                            device.updated_at = options.recover_field(node_key, index, Timestamp::from_str(s))?
                         }
                         (ModelNodeKey::MacAddress, JsonScalarValue::String(s)) => {
-                            device.mac_address = MacAddress::from_str(s)?
+                            device.mac_address = options.recover_field(node_key, index, MacAddress::from_str(s))?
                         }
                         (ModelNodeKey::BtMacAddress, JsonScalarValue::String(s)) => {
-                            device.bt_mac_address = MacAddress::from_str(s)?
+                            device.bt_mac_address = options.recover_field(node_key, index, MacAddress::from_str(s))?
                         }
-                        (ModelNodeKey::SerialNumber, JsonScalarValue::String(s)) => {
-                            device.serial_number = copy_string_option(s, options)?;
+                        (ModelNodeKey::SerialNumber, value) if is_string(&value) => {
+                            options.copy_piece(&mut device.serial_number, node_key, index, value, &mut statistics)?;
                         }
-                        (ModelNodeKey::FirmwareVersion, JsonScalarValue::String(s)) => {
-                            device.firmware_version = copy_string_option(s, options)?;
+                        (ModelNodeKey::FirmwareVersion, value) if is_string(&value) => {
+                            options.copy_piece(&mut device.firmware_version, node_key, index, value, &mut statistics)?;
                         }
-                        (ModelNodeKey::TemperatureOffset, JsonScalarValue::Number(n)) => {
-                            device.temperature_offset = n.into()
+                        // Following pieces of a string longer than the parser buffer.
+                        (_, JsonScalarValue::ContinuingString(_, offset, _)) if offset > 0 => {}
+                        (ModelNodeKey::Id | ModelNodeKey::CreatedAt | ModelNodeKey::UpdatedAt | ModelNodeKey::MacAddress | ModelNodeKey::BtMacAddress, JsonScalarValue::ContinuingString(..)) => {
+                            options.recover_field(node_key, index, Err::<(), _>(ModelNodeParseError::StringTooLong))?
                         }
-                        (ModelNodeKey::HumidityOffset, JsonScalarValue::Number(n)) => {
-                            device.humidity_offset = n.into()
+                        (ModelNodeKey::TemperatureOffset | ModelNodeKey::HumidityOffset, value) => {
+                            options.check_number(node_key, &value, path.path(), &mut violations);
+                            let offset = options.recover_field(node_key, index, coerce_number(&value))?;
+                            if node_key == ModelNodeKey::TemperatureOffset {
+                                device.temperature_offset = offset;
+                            } else {
+                                device.humidity_offset = offset;
+                            }
                         }
-                        _ => {} // Ignore unknown nodes.
+                        _ => options.report_violation(ViolationKind::WrongType(node_key), path.path(), &mut violations),
                     }
                 }
                 DevicesParserState::DeviceMap
@@ -241,19 +486,29 @@ where
                         callback(&device, None);
                         DevicesParserState::UsersArray
                     }
-                    _ => {
+                    key => {
+                        if let Some(key) = key.filter(|key| options.is_selected(*key)) {
+                            options.report_violation(ViolationKind::WrongType(key), path.path(), &mut violations);
+                        }
+                        statistics.skipped_containers += 1;
                         unknown_array_depth += 1;
+                        unknown_parent = DevicesParserState::DeviceMap;
                         DevicesParserState::UnknownMapArray
                     }
                 }
             }
             (DevicesParserState::DeviceMap, JsonNode::StartMap) => match node_key.take() {
-                Some(ModelNodeKey::NewestEvents) => {
+                Some(ModelNodeKey::NewestEvents) if options.is_selected(ModelNodeKey::NewestEvents) => {
                     subnode = DeviceSubNode::NewestEvents(NewestEvents::default());
                     DevicesParserState::NewestEventsMap
                 }
-                _ => {
+                key => {
+                    if let Some(key) = key.filter(|key| *key != ModelNodeKey::NewestEvents && options.is_selected(*key)) {
+                        options.report_violation(ViolationKind::WrongType(key), path.path(), &mut violations);
+                    }
+                    statistics.skipped_containers += 1;
                     unknown_map_depth += 1;
+                    unknown_parent = DevicesParserState::DeviceMap;
                     DevicesParserState::UnknownMapArray
                 }
             },
@@ -263,39 +518,49 @@ where
                 DevicesParserState::DeviceMap
             } // Return to device map state
             (DevicesParserState::UsersArray, JsonNode::StartMap) => {
+                sub_seen = FieldMask::NONE;
                 subnode = DeviceSubNode::User(User::default());
                 DevicesParserState::UserMap
             }
             // Process user map
             (DevicesParserState::UserMap, JsonNode::Value(value)) => {
                 if let DeviceSubNode::User(ref mut user) = &mut subnode {
-                    if let Some(node_key) = node_key.take() {
+                    if let Some(node_key) = options.take_key(&mut node_key, &value) {
                         match (node_key, value) {
                             (ModelNodeKey::Id, JsonScalarValue::String(s)) => {
-                                user.id = Uuid::from_str(s)?
+                                user.id = options.recover_field(node_key, index, parse_uuid(s))?
                             }
-                            (ModelNodeKey::NickName, JsonScalarValue::String(s)) => {
-                                user.nickname = copy_string_option(s, options)?;
+                            (ModelNodeKey::NickName, value) if is_string(&value) => {
+                                options.copy_piece(&mut user.nickname, node_key, index, value, &mut statistics)?;
+                            }
+                            // Following pieces of a string longer than the parser buffer.
+                            (_, JsonScalarValue::ContinuingString(_, offset, _)) if offset > 0 => {}
+                            (ModelNodeKey::Id, JsonScalarValue::ContinuingString(..)) => {
+                                options.recover_field(node_key, index, Err::<(), _>(ModelNodeParseError::StringTooLong))?
                             }
                             (ModelNodeKey::SuperUser, JsonScalarValue::Boolean(v)) => {
                                 user.superuser = v
                             }
-                            _ => {} // Ignore unknown nodes.
+                            _ => options.report_violation(ViolationKind::WrongType(node_key), path.path(), &mut violations),
                         }
                     }
                 }
                 DevicesParserState::UserMap
             }
             (DevicesParserState::UserMap, JsonNode::EndMap) => {
+                options.check_required(REQUIRED_USER_FIELDS, sub_seen, path.path(), &mut violations);
                 callback(&device, Some(&subnode));
+                statistics.sub_nodes += 1;
                 DevicesParserState::UsersArray // Return to users array.
             }
             // Process newest_events map
             (DevicesParserState::NewestEventsMap, JsonNode::EndMap) => {
                 callback(&device, Some(&subnode));
+                statistics.sub_nodes += 1;
                 DevicesParserState::DeviceMap // Return to device map state
             }
-            (DevicesParserState::NewestEventsMap, JsonNode::StartMap) => {
+            // Sensors not known by the parser are skipped below.
+            (DevicesParserState::NewestEventsMap, JsonNode::StartMap) if node_key.is_some() => {
                 let newest_events = if let DeviceSubNode::NewestEvents(ref mut newest_events) =
                     &mut subnode
                 {
@@ -306,25 +571,19 @@ where
                     );
                 };
 
-                match node_key.take() {
-                    Some(ModelNodeKey::Te) => {
-                        newest_events.temperature = Some(SensorValue::default());
-                        DevicesParserState::NewestEventMap(NewestEventType::Temperature)
-                    }
-                    Some(ModelNodeKey::Hu) => {
-                        newest_events.humidity = Some(SensorValue::default());
-                        DevicesParserState::NewestEventMap(NewestEventType::Humidity)
-                    }
-                    Some(ModelNodeKey::Il) => {
-                        newest_events.illumination = Some(SensorValue::default());
-                        DevicesParserState::NewestEventMap(NewestEventType::Illumination)
-                    }
-                    Some(ModelNodeKey::Mo) => {
-                        newest_events.motion = Some(SensorValue::default());
-                        DevicesParserState::NewestEventMap(NewestEventType::Motion)
-                    }
-                    _ => return Err(ModelNodeParseError::UnknownNewestEventsType),
+                let (sensor_value, newest_event_type) = match node_key.take() {
+                    Some(ModelNodeKey::Te) => (&mut newest_events.temperature, NewestEventType::Temperature),
+                    Some(ModelNodeKey::Hu) => (&mut newest_events.humidity, NewestEventType::Humidity),
+                    Some(ModelNodeKey::Il) => (&mut newest_events.illumination, NewestEventType::Illumination),
+                    Some(ModelNodeKey::Mo) => (&mut newest_events.motion, NewestEventType::Motion),
+                    _ => return Err(ModelNodeParseError::UnexpectedParserState),
+                };
+                // Values of an unselected sensor are skipped as the sensor value is left None.
+                if options.is_selected(newest_event_type.key()) {
+                    *sensor_value = Some(SensorValue::default());
                 }
+                sub_seen = FieldMask::NONE;
+                DevicesParserState::NewestEventMap(newest_event_type)
             }
             // Process maps in a newest_events map
             (
@@ -333,28 +592,60 @@ where
             ) => {
                 if let DeviceSubNode::NewestEvents(ref mut newest_events) = &mut subnode {
                     let sensor_value = match newest_event_type {
-                        NewestEventType::Temperature => newest_events.temperature.as_mut().unwrap(),
-                        NewestEventType::Humidity => newest_events.humidity.as_mut().unwrap(),
-                        NewestEventType::Illumination => {
-                            newest_events.illumination.as_mut().unwrap()
-                        }
-                        NewestEventType::Motion => newest_events.motion.as_mut().unwrap(),
+                        NewestEventType::Temperature => newest_events.temperature.as_mut(),
+                        NewestEventType::Humidity => newest_events.humidity.as_mut(),
+                        NewestEventType::Illumination => newest_events.illumination.as_mut(),
+                        NewestEventType::Motion => newest_events.motion.as_mut(),
                     };
-                    match (node_key.take(), value) {
-                        (Some(ModelNodeKey::Val), JsonScalarValue::Number(n)) => {
-                            sensor_value.val = n.into()
-                        }
-                        (Some(ModelNodeKey::CreatedAt), JsonScalarValue::String(s)) => {
-                            sensor_value.created_at = Timestamp::from_str(s)?
+                    let node_key = options.take_key(&mut node_key, &value);
+                    if let Some(sensor_value) = sensor_value {
+                        match (node_key, value) {
+                            // Following pieces of a string longer than the parser buffer.
+                            (_, JsonScalarValue::ContinuingString(_, offset, _)) if offset > 0 => {}
+                            (Some(ModelNodeKey::Val), value) => {
+                                options.check_number(ModelNodeKey::Val, &value, path.path(), &mut violations);
+                                sensor_value.val = options.recover_field(ModelNodeKey::Val, index, coerce_number(&value))?
+                            }
+                            (Some(ModelNodeKey::CreatedAt), JsonScalarValue::String(s)) => {
+                                sensor_value.created_at = options.recover_field(ModelNodeKey::CreatedAt, index, Timestamp::from_str(s))?
+                            }
+                            (Some(ModelNodeKey::CreatedAt), JsonScalarValue::ContinuingString(..)) => {
+                                options.recover_field(ModelNodeKey::CreatedAt, index, Err::<(), _>(ModelNodeParseError::StringTooLong))?
+                            }
+                            (Some(key), _) => options.report_violation(ViolationKind::WrongType(key), path.path(), &mut violations),
+                            (None, _) => {}
                         }
-                        _ => {}
                     }
                 }
                 DevicesParserState::NewestEventMap(newest_event_type)
             }
             (DevicesParserState::NewestEventMap(_), JsonNode::EndMap) => {
+                options.check_required(REQUIRED_SENSOR_VALUE_FIELDS, sub_seen, path.path(), &mut violations);
+                DevicesParserState::NewestEventsMap
+            }
+            (DevicesParserState::NewestEventsMap, JsonNode::Value(_)) => {
+                if let Some(key) = node_key.take().filter(|key| options.is_selected(*key)) {
+                    options.report_violation(ViolationKind::WrongType(key), path.path(), &mut violations);
+                }
                 DevicesParserState::NewestEventsMap
             }
+            // Skip maps and arrays of unknown keys in sub nodes.
+            (
+                map_state @ (DevicesParserState::UserMap | DevicesParserState::NewestEventsMap | DevicesParserState::NewestEventMap(_)),
+                node @ (JsonNode::StartMap | JsonNode::StartArray),
+            ) => {
+                if let Some(key) = node_key.take().filter(|key| options.is_selected(*key)) {
+                    options.report_violation(ViolationKind::WrongType(key), path.path(), &mut violations);
+                }
+                statistics.skipped_containers += 1;
+                if matches!(node, JsonNode::StartMap) {
+                    unknown_map_depth += 1;
+                } else {
+                    unknown_array_depth += 1;
+                }
+                unknown_parent = map_state;
+                DevicesParserState::UnknownMapArray
+            }
 
             // Process unknown nodes in device nodes.
             (DevicesParserState::UnknownMapArray, JsonNode::StartArray) => {
@@ -368,7 +659,7 @@ where
             (DevicesParserState::UnknownMapArray, JsonNode::EndArray) => {
                 unknown_array_depth -= 1;
                 if unknown_array_depth == 0 && unknown_map_depth == 0 {
-                    DevicesParserState::DeviceMap
+                    unknown_parent
                 } else {
                     DevicesParserState::UnknownMapArray
                 }
@@ -376,31 +667,43 @@ where
             (DevicesParserState::UnknownMapArray, JsonNode::EndMap) => {
                 unknown_map_depth -= 1;
                 if unknown_array_depth == 0 && unknown_map_depth == 0 {
-                    DevicesParserState::DeviceMap
+                    unknown_parent
                 } else {
                     DevicesParserState::UnknownMapArray
                 }
             }
             (DevicesParserState::UnknownMapArray, _) => DevicesParserState::UnknownMapArray,    // Ignore unknown values in unknown map/array.
-            (state, json_node) => {
-                let mut error = UnexpectedNodeError::new();
-                write!(&mut error, "{:?}", (state, json_node)).ok();
-                return Err(ModelNodeParseError::UnexpectedNode(error));
+            (_, json_node) => {
+                return Err(ModelNodeParseError::UnexpectedNode(NodeKind::from(&json_node)));
             }
         };
         state = new_state;
         Ok(ParserCallbackAction::Nothing)
-    })? {}
-    Ok(())
+    }).map_err(|error| path.error(error, reader.bytes_read()))? {}
+    if violations > 0 {
+        return Err(path.error(JsonParserError::Callback(ModelNodeParseError::SchemaViolation), reader.bytes_read()));
+    }
+    statistics.bytes_read = reader.bytes_read();
+    statistics.max_depth = path.max_depth();
+    Ok(statistics)
 }
 
 #[cfg(test)]
 mod test {
     use fuga_json_seq_parser::BufferReader;
+    use fuga_json_seq_parser::ParserError as JsonParserError;
+    use fuga_json_seq_parser::Parser as JsonParser;
+    use heapless::String;
     use uuid::uuid;
 
+    use crate::diagnostics::{DiagnosticKind, Diagnostics};
+    use crate::location::PathSegment;
+    use crate::schema::{ViolationKind, Violations};
+    use crate::unknown_keys::{UnknownKeyKind, UnknownKeys};
+
     use super::*;
 
+    #[allow(clippy::needless_as_bytes)]
     fn create_reader<'a>(input: &'a str) -> (usize, BufferReader<'a>) {
         let total_length = input.as_bytes().len();
         (total_length, BufferReader::new(input.as_bytes()))
@@ -416,6 +719,46 @@ mod test {
         let parsed = MacAddress::from_str("f0-08-d1-00-11-22").unwrap();
         assert_eq!(parsed, MacAddress([0xf0, 0x08, 0xd1, 0x00, 0x11, 0x22]));
     }
+    #[test]
+    fn test_parse_mac_address_dotted_and_bare() {
+        let expected = MacAddress([0xf0, 0x08, 0xd1, 0x00, 0x11, 0x22]);
+        assert_eq!(MacAddress::from_str("f008.d100.1122").unwrap(), expected);
+        assert_eq!(MacAddress::from_str("F008D1001122").unwrap(), expected);
+        assert!(MacAddress::from_str("f008.d100.11").is_err());
+        assert!(MacAddress::from_str("f008d100112233").is_err());
+    }
+    #[test]
+    fn test_parse_mac_address_trailing_garbage() {
+        assert!(MacAddress::from_str("aa:bb:cc:dd:ee:ffjunk").is_err());
+        assert!(MacAddress::from_str("aa-bb-cc-dd-ee-ff:00").is_err());
+        assert!(MacAddress::from_str("aabb.ccdd.eeff.").is_err());
+        assert!(MacAddress::from_str("aabbccddeeff ").is_err());
+    }
+    #[test]
+    fn test_format_mac_address() {
+        let mac_address = MacAddress([0x24, 0x6f, 0x28, 0x00, 0x11, 0xab]);
+        let format = |args: fmt::Arguments| {
+            let mut s = String::<32>::new();
+            s.write_fmt(args).unwrap();
+            s
+        };
+        assert_eq!(format(format_args!("{}", mac_address)), "24:6f:28:00:11:ab");
+        assert_eq!(format(format_args!("{:x}", mac_address)), "246f280011ab");
+        assert_eq!(format(format_args!("{:X}", mac_address)), "246F280011AB");
+        assert_eq!(format(format_args!("{:#x}", mac_address)), "0x246f280011ab");
+        assert_eq!(format(format_args!("{:#X}", mac_address)), "0x246F280011AB");
+        assert_eq!(format(format_args!("{}", mac_address.display(MacAddressStyle::Hyphen))), "24-6f-28-00-11-ab");
+        assert_eq!(format(format_args!("{}", mac_address.display(MacAddressStyle::Dotted))), "246f.2800.11ab");
+        assert_eq!(format(format_args!("{:#}", mac_address.display(MacAddressStyle::Colon))), "24:6F:28:00:11:AB");
+        let dotted = format(format_args!("{}", mac_address.display(MacAddressStyle::Dotted)));
+        assert_eq!(MacAddress::from_str(&dotted).unwrap(), mac_address);
+    }
+    #[test]
+    fn test_mac_address_vendor() {
+        assert_eq!(MacAddress::from_str("c8:2b:96:00:11:22").unwrap().vendor(), Some("Espressif"));
+        assert_eq!(MacAddress::from_str("00:00:5e:00:53:01").unwrap().vendor(), None);
+        assert_eq!(MacAddress::from_str("f0:08:d1:00:11:22").unwrap().oui(), [0xf0, 0x08, 0xd1]);
+    }
 
     #[test]
     fn test_parse_empty_devices() {
@@ -431,6 +774,7 @@ mod test {
         .unwrap();
     }
     #[test]
+    #[allow(clippy::single_match)]
     fn test_parse_devices() {
         let (length, mut reader) = create_reader(include_str!("../data/devices.json"));
         let expected_devices = [
@@ -492,4 +836,260 @@ mod test {
         )
         .unwrap();
     }
+
+    #[derive(Clone, Debug, Default, PartialEq)]
+    struct SmallCapacities;
+
+    impl Capacities for SmallCapacities {
+        type Nickname = String<8>;
+        type DeviceName = String<8>;
+        type FirmwareVersion = String<20>;
+        type ModelName = String<8>;
+        type Manufacturer = String<8>;
+        type RemoteName = String<8>;
+        type Series = String<8>;
+        type Image = String<8>;
+        type Country = String<2>;
+        type EchonetLiteName = String<8>;
+        type EchonetLiteValue = String<8>;
+        type DevicesParser = JsonParser<40, 5>;
+        type AppliancesParser = JsonParser<40, 10>;
+    }
+
+    #[test]
+    fn test_parse_devices_with_capacities() {
+        let (length, mut reader) = create_reader(include_str!("../data/devices.json"));
+        let mut names: heapless::Vec<String<8>, 3> = heapless::Vec::new();
+        let diagnostics = Diagnostics::<8>::new();
+        let statistics = read_devices_with_capacities::<SmallCapacities, _, _>(
+            &mut reader,
+            Some(length),
+            &ParserOptions::default().with_diagnostics(&diagnostics),
+            |device, sub_node| {
+                if sub_node.is_none() {
+                    names.push(device.name.clone()).unwrap();
+                }
+            },
+        )
+        .unwrap();
+        assert_eq!(names.as_slice(), &[String::<8>::from("test rem"), String::from("Remo"), String::from("Remo E l")]);
+        let truncated: heapless::Vec<_, 8> = diagnostics.take().iter().map(|d| (d.key, d.index, d.kind)).collect();
+        assert_eq!(
+            truncated.as_slice(),
+            &[
+                (ModelNodeKey::Name, 0, DiagnosticKind::Truncated { length: 21 }),
+                (ModelNodeKey::NickName, 0, DiagnosticKind::Truncated { length: 9 }),
+                (ModelNodeKey::NickName, 1, DiagnosticKind::Truncated { length: 9 }),
+                (ModelNodeKey::Name, 2, DiagnosticKind::Truncated { length: 11 }),
+                (ModelNodeKey::NickName, 2, DiagnosticKind::Truncated { length: 9 }),
+            ]
+        );
+        assert_eq!(statistics.truncated_strings, truncated.len());
+    }
+
+    #[test]
+    fn test_parse_devices_long_strings() {
+        // Longer than the parser buffer, so the name is passed to the parser callback in pieces.
+        let name = "リビングルームの窓際に置いてあるネイチャーリモ";
+        assert!(name.len() > REQUIRED_DEVICES_PARSER_BUFFER_LEN);
+        let input = r#"[{"name":"リビングルームの窓際に置いてあるネイチャーリモ","firmware_version":"Remo/1.0.0","users":[]}]"#;
+        let (length, mut reader) = create_reader(input);
+        let diagnostics = Diagnostics::<4>::new();
+        let mut names: heapless::Vec<String<MAX_DEVICE_NAME_LEN>, 1> = heapless::Vec::new();
+        read_devices(&mut reader, Some(length), &ParserOptions::default().with_diagnostics(&diagnostics), |device, _| {
+            assert_eq!(device.firmware_version.as_str(), "Remo/1.0.0");
+            names.push(device.name.clone()).unwrap();
+        })
+        .unwrap();
+        assert_eq!(names[0].as_str(), "リビングルームの窓際に置いてある");
+        let truncated: heapless::Vec<_, 4> = diagnostics.take().iter().map(|d| (d.key, d.index, d.kind)).collect();
+        assert_eq!(truncated.as_slice(), &[(ModelNodeKey::Name, 0, DiagnosticKind::Truncated { length: name.len() })]);
+
+        // A too long value of a field which is not a string in the models is invalid.
+        let input = r#"[{"id":"f262cb0c-a853-47bb-9559-44d0f2c4d6e4-f262cb0c-a853-47bb-9559-44d0f2c4d6e4","name":"Remo","users":[]}]"#;
+        let (length, mut reader) = create_reader(input);
+        read_devices(&mut reader, Some(length), &ParserOptions::default().lenient(&diagnostics), |device, _| {
+            assert_eq!(device.name.as_str(), "Remo");
+        })
+        .unwrap();
+        let invalid: heapless::Vec<_, 4> = diagnostics.take().iter().map(|d| (d.key, d.kind)).collect();
+        assert_eq!(invalid.as_slice(), &[(ModelNodeKey::Id, DiagnosticKind::Invalid(ModelNodeParseError::StringTooLong))]);
+    }
+
+    #[test]
+    fn test_parse_devices_statistics() {
+        let input = r#"[{"name":"Remo","extra":1,"tags":["a",{"b":{"c":1}}],
+            "users":[{"id":"b0e1c2d3-1234-4abc-8def-0123456789ab","nickname":"user","x":1}],
+            "newest_events":{"te":{"val":24.5,"created_at":"2022-11-18T06:42:59Z"}}},
+            {"name":"リビングルームの窓際に置いてあるネイチャーリモ","users":[]}]"#;
+        let (length, mut reader) = create_reader(input);
+        let statistics = read_devices(&mut reader, Some(length), &ParserOptions::default(), |_, _| {}).unwrap();
+        assert_eq!(
+            statistics,
+            ReadStatistics {
+                elements: 2,
+                sub_nodes: 2,
+                unknown_keys: 3,
+                skipped_containers: 1,
+                bytes_read: length,
+                max_depth: 5,
+                // The name longer than the parser buffer.
+                truncated_strings: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_devices_without_users() {
+        let input = r#"[{"name":"Remo","newest_events":{"te":{"val":24.5,"created_at":"2022-11-18T06:42:59Z"}}},
+            {"name":"Remo mini","users":[]}]"#;
+        let (length, mut reader) = create_reader(input);
+        let mut devices = 0;
+        let statistics = read_devices(&mut reader, Some(length), &ParserOptions::default(), |_, sub_node| {
+            if sub_node.is_none() {
+                devices += 1;
+            }
+        })
+        .unwrap();
+        // The device without `users` is counted though it is not passed to the callback without a sub node.
+        assert_eq!(devices, 1);
+        assert_eq!((statistics.elements, statistics.sub_nodes), (2, 1));
+    }
+
+    #[test]
+    fn test_parse_devices_with_fields() {
+        let (length, mut reader) = create_reader(
+            r#"[{"name":"Remo","id":"not-a-uuid","mac_address":"invalid","users":[{"id":"invalid","nickname":"user"}],
+            "newest_events":{"te":{"val":24.5,"created_at":"invalid"},"hu":{"val":60,"created_at":"invalid"}}}]"#,
+        );
+        let options = ParserOptions::default().with_fields(
+            [ModelNodeKey::Name, ModelNodeKey::NewestEvents, ModelNodeKey::Te, ModelNodeKey::Val].into_iter().collect(),
+        );
+        let mut callbacks = 0;
+        read_devices(&mut reader, Some(length), &options, |device, sub_node| {
+            assert_eq!(device.name.as_str(), "Remo");
+            assert_eq!(device.mac_address, MacAddress::default());
+            match sub_node {
+                Some(DeviceSubNode::User(user)) => assert!(user.nickname.is_empty()),
+                Some(DeviceSubNode::NewestEvents(events)) => {
+                    assert_eq!(events.temperature, Some(SensorValue { val: 24.5, created_at: Timestamp::default() }));
+                    assert_eq!(events.humidity, None);
+                }
+                None => {}
+            }
+            callbacks += 1;
+        })
+        .unwrap();
+        assert_eq!(callbacks, 3);
+    }
+
+    #[test]
+    fn test_parse_devices_error_location() {
+        let input = r#"[{"name":"a","users":[]},{"name":"b","users":[{"id":"6e4c4b6f-6ae2-4bda-8e2b-ad8e5b9f7e0a"},{"id":"bad"}]}]"#;
+        let (length, mut reader) = create_reader(input);
+        let error = read_devices(&mut reader, Some(length), &ParserOptions::default(), |_, _| {}).unwrap_err();
+        assert!(matches!(error.error, JsonParserError::Callback(ModelNodeParseError::UuidParseError(_))));
+        assert_eq!(error.location.index(), Some(1));
+        assert!(error.location.offset > input.find("bad").unwrap());
+        let mut path = String::<64>::new();
+        write!(&mut path, "{}", error.location).unwrap();
+        assert!(path.starts_with("[1].users[1].id at byte "), "{}", path);
+    }
+
+    #[test]
+    fn test_parse_devices_strict() {
+        let input = r#"[{"id":"f262cb0c-a853-47bb-9559-44d0f2c4d6e4","name":"Remo","created_at":"2022-11-18T06:42:59Z",
+            "updated_at":"2022-11-18T06:42:59Z","mac_address":"f0:08:d1:00:11:22","serial_number":"1W300000000000",
+            "firmware_version":"Remo/1.0.0","temperature_offset":"0","humidity_offset":0,"extra":1,
+            "users":[{"id":"b0e1c2d3-1234-4abc-8def-0123456789ab","nickname":"user"}]}]"#;
+        let (length, mut reader) = create_reader(input);
+        read_devices(&mut reader, Some(length), &ParserOptions::default(), |_, _| {}).unwrap();
+
+        let violations = Violations::<4>::new();
+        let (length, mut reader) = create_reader(input);
+        let error = read_devices(&mut reader, Some(length), &ParserOptions::default().strict(&violations), |_, _| {}).unwrap_err();
+        assert!(matches!(error.error, JsonParserError::Callback(ModelNodeParseError::SchemaViolation)));
+        let violations = violations.take();
+        let kinds: heapless::Vec<_, 4> = violations.iter().map(|violation| violation.kind).collect();
+        assert_eq!(
+            kinds.as_slice(),
+            &[
+                ViolationKind::WrongType(ModelNodeKey::TemperatureOffset),
+                ViolationKind::UnknownKey,
+                ViolationKind::MissingField(ModelNodeKey::SuperUser),
+            ]
+        );
+        let mut s = String::<64>::new();
+        write!(&mut s, "{}", violations[2]).unwrap();
+        assert_eq!(s.as_str(), "missing field at [0].users[0].superuser");
+        assert_eq!(violations[1].path.as_slice(), &[PathSegment::Index(0), PathSegment::UnknownKey]);
+        // `commissionee_fabrics` is in the API, though it is not modeled by the parser.
+        let violations = Violations::<4>::new();
+        let unknown_keys = UnknownKeys::<4, 32>::new();
+        let options = ParserOptions::default().strict(&violations).with_unknown_keys(&unknown_keys);
+        let (length, mut reader) = create_reader(include_str!("../data/devices.json"));
+        let statistics = read_devices(&mut reader, Some(length), &options, |_, _| {}).unwrap();
+        assert!(violations.is_empty());
+        assert!(statistics.unknown_keys > 0);
+        let unknown_keys = unknown_keys.take();
+        assert_eq!(unknown_keys[0].key.as_str(), "commissionee_fabrics");
+        assert!(unknown_keys.iter().all(|unknown_key| unknown_key.kind == UnknownKeyKind::Unmodeled));
+        let violations = Violations::<4>::new();
+        let unknown_keys = UnknownKeys::<4, 32>::new();
+        let options = ParserOptions::default().strict(&violations).with_unknown_keys(&unknown_keys).with_unmodeled_keys(&[]);
+        let (length, mut reader) = create_reader(include_str!("../data/devices.json"));
+        read_devices(&mut reader, Some(length), &options, |_, _| {}).unwrap_err();
+        assert_eq!(violations.take()[0].path.last(), Some(&PathSegment::UnknownKey));
+        assert_eq!(unknown_keys.take()[0].kind, UnknownKeyKind::Unknown);
+    }
+
+    #[test]
+    fn test_parse_devices_strict_long_strings() {
+        // A nickname longer than the parser buffer is a string, though it is passed to the parser callback in pieces.
+        let input = r#"[{"id":"f262cb0c-a853-47bb-9559-44d0f2c4d6e4","name":"Remo","created_at":"2022-11-18T06:42:59Z",
+            "updated_at":"2022-11-18T06:42:59Z","mac_address":"f0:08:d1:00:11:22","serial_number":"1W300000000000",
+            "firmware_version":"Remo/1.0.0","temperature_offset":0,"humidity_offset":0,
+            "users":[{"id":"b0e1c2d3-1234-4abc-8def-0123456789ab","nickname":"リビングルームの窓際に置いてあるネイチャーリモの持ち主","superuser":true}]}]"#;
+        let violations = Violations::<4>::new();
+        let (length, mut reader) = create_reader(input);
+        let mut superuser = false;
+        read_devices(&mut reader, Some(length), &ParserOptions::default().strict(&violations), |_, sub_node| {
+            if let Some(DeviceSubNode::User(user)) = sub_node {
+                assert!(user.nickname.starts_with("リビングルーム"));
+                superuser = user.superuser;
+            }
+        })
+        .unwrap();
+        assert!(violations.is_empty());
+        assert!(superuser);
+    }
+
+    #[test]
+    fn test_parse_devices_misplaced_keys() {
+        // `nickname` is known in user maps, `val` in sensor values and `co2` is not known at all.
+        let input = r#"[{"name":"Remo","nickname":"remo","users":[{"nickname":"user","name":"x"}],
+            "newest_events":{"co2":{"val":400,"created_at":"2022-11-18T06:42:59Z"},"val":1,"te":{"val":24.5,"hu":60}}}]"#;
+        let unknown_keys = UnknownKeys::<8, 16>::new();
+        let (length, mut reader) = create_reader(input);
+        let mut temperature = None;
+        let statistics = read_devices(&mut reader, Some(length), &ParserOptions::default().with_unknown_keys(&unknown_keys), |_, sub_node| {
+            if let Some(DeviceSubNode::NewestEvents(events)) = sub_node {
+                temperature = events.temperature.as_ref().map(|value| value.val);
+            }
+        })
+        .unwrap();
+        assert_eq!(temperature, Some(24.5));
+        assert_eq!((statistics.unknown_keys, statistics.skipped_containers), (5, 1));
+        let keys: heapless::Vec<_, 8> = unknown_keys.take().iter().map(|unknown_key| unknown_key.key.clone()).collect();
+        assert_eq!(keys.as_slice(), &["nickname", "name", "co2", "val", "hu"]);
+
+        let violations = Violations::<8>::new();
+        let (length, mut reader) = create_reader(input);
+        read_devices(&mut reader, Some(length), &ParserOptions::default().strict(&violations), |_, _| {}).unwrap_err();
+        let violations = violations.take();
+        let unknown: heapless::Vec<_, 8> = violations.iter().filter(|violation| violation.kind == ViolationKind::UnknownKey).collect();
+        assert_eq!(unknown.len(), 5);
+        assert!(unknown.iter().all(|violation| violation.path.last() == Some(&PathSegment::UnknownKey)));
+        assert!(!violations.iter().any(|violation| matches!(violation.kind, ViolationKind::WrongType(_))));
+    }
 }
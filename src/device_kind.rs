@@ -0,0 +1,419 @@
+// Hardware model and capability detection of Remo devices.
+// Copyright 2022-2023 Kenta Ida 
+// SPDX-License-Identifier: MIT
+//
+use core::{cmp::Ordering, fmt, str::FromStr};
+
+use heapless::String;
+
+use crate::common_types::*;
+use crate::config::*;
+use crate::device::Device;
+
+/// Hardware model of a Remo device.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum DeviceKind {
+    /// Nature Remo (1st generation)
+    Remo,
+    Remo2,
+    Remo3,
+    RemoMini,
+    RemoMini2,
+    RemoNano,
+    RemoE,
+    RemoELite,
+    #[default]
+    Unknown,
+}
+
+/// Sensors and features available on a device.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Capabilities {
+    pub temperature: bool,
+    pub humidity: bool,
+    pub illuminance: bool,
+    pub motion: bool,
+    pub smart_meter: bool,
+}
+
+impl Capabilities {
+    const NONE: Self = Self {
+        temperature: false,
+        humidity: false,
+        illuminance: false,
+        motion: false,
+        smart_meter: false,
+    };
+    const ALL_SENSORS: Self = Self {
+        temperature: true,
+        humidity: true,
+        illuminance: true,
+        motion: true,
+        ..Self::NONE
+    };
+    const TEMPERATURE: Self = Self {
+        temperature: true,
+        ..Self::NONE
+    };
+    const SMART_METER: Self = Self {
+        smart_meter: true,
+        ..Self::NONE
+    };
+}
+
+/// Product names in the firmware version, e.g. `Remo-mini` of `Remo-mini/1.10.0`.
+/// `Remo`, `Remo-mini` and `Remo-E-lite` are confirmed by `data/devices.json`.
+const PRODUCT_NAMES: &[(&str, DeviceKind)] = &[
+    ("Remo", DeviceKind::Remo),
+    ("Remo-2", DeviceKind::Remo2), // Not confirmed.
+    ("Remo-3", DeviceKind::Remo3), // Not confirmed.
+    ("Remo-mini", DeviceKind::RemoMini),
+    ("Remo-mini-2", DeviceKind::RemoMini2), // Not confirmed.
+    ("Remo-nano", DeviceKind::RemoNano), // Not confirmed.
+    ("Remo-E", DeviceKind::RemoE), // Not confirmed.
+    ("Remo-E-lite", DeviceKind::RemoELite),
+];
+
+/// Prefixes of the serial number, used if the firmware version does not identify the model.
+/// Taken from the devices of the same product names in `data/devices.json`.
+/// The prefixes of the other models are not known, so they are detected only from the firmware version.
+const SERIAL_NUMBER_PREFIXES: &[(&str, DeviceKind)] = &[
+    ("1W", DeviceKind::Remo),
+    ("2B", DeviceKind::RemoMini),
+    ("4W", DeviceKind::RemoELite),
+];
+
+impl DeviceKind {
+    /// Detect the model from the product name in the firmware version.
+    pub fn from_product(product: &str) -> Self {
+        PRODUCT_NAMES
+            .iter()
+            .find(|(name, _)| *name == product)
+            .map_or(Self::Unknown, |(_, kind)| *kind)
+    }
+
+    /// Detect the model from the prefix of the serial number.
+    pub fn from_serial_number(serial_number: &str) -> Self {
+        SERIAL_NUMBER_PREFIXES
+            .iter()
+            .find(|(prefix, _)| serial_number.starts_with(prefix))
+            .map_or(Self::Unknown, |(_, kind)| *kind)
+    }
+
+    /// Capabilities of the model. `Unknown` has no capabilities.
+    /// Remo, Remo mini and Remo E lite are confirmed by the sensors in `newest_events` of `data/devices.json`
+    /// and the smart meter bound to Remo E lite in `data/appliances.json`. The others follow the product specifications.
+    pub fn capabilities(&self) -> Capabilities {
+        match self {
+            Self::Remo | Self::Remo2 | Self::Remo3 => Capabilities::ALL_SENSORS,
+            Self::RemoMini | Self::RemoMini2 => Capabilities::TEMPERATURE,
+            Self::RemoE | Self::RemoELite => Capabilities::SMART_METER,
+            Self::RemoNano | Self::Unknown => Capabilities::NONE,
+        }
+    }
+}
+
+/// Firmware version in the form of `<product>/<major>.<minor>.<patch>[-<prerelease>][+<build>]`.
+/// Versions are ordered by the precedence of semantic versioning, i.e. by the version number,
+/// then by the prerelease where a prerelease is older than its release.
+/// Versions of the same precedence are ordered by the product name and the build metadata to be consistent with `Eq`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FirmwareVersion {
+    pub product: String<MAX_FIRMWARE_VERSION_LEN>,
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+    /// Prerelease identifiers after `-`, e.g. `rc.1`. Empty for a release.
+    pub prerelease: String<MAX_FIRMWARE_VERSION_LEN>,
+    /// Build metadata after `+`, which does not affect the precedence.
+    pub build: String<MAX_FIRMWARE_VERSION_LEN>,
+}
+
+impl FirmwareVersion {
+    pub fn kind(&self) -> DeviceKind {
+        DeviceKind::from_product(&self.product)
+    }
+
+    /// Version number as a tuple of `(major, minor, patch)`.
+    pub fn version(&self) -> (u16, u16, u16) {
+        (self.major, self.minor, self.patch)
+    }
+}
+
+/// Compare prerelease identifiers by the precedence of semantic versioning.
+/// Numeric identifiers are compared numerically and are lower than alphanumeric ones.
+fn compare_prerelease(a: &str, b: &str) -> Ordering {
+    match (a.is_empty(), b.is_empty()) {
+        (true, true) => return Ordering::Equal,
+        (true, false) => return Ordering::Greater,
+        (false, true) => return Ordering::Less,
+        (false, false) => {}
+    }
+    let is_numeric = |identifier: &str| identifier.bytes().all(|c| c.is_ascii_digit());
+    let (mut a, mut b) = (a.split('.'), b.split('.'));
+    loop {
+        let ordering = match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => match (is_numeric(a), is_numeric(b)) {
+                (true, true) => a.len().cmp(&b.len()).then_with(|| a.cmp(b)),
+                (true, false) => Ordering::Less,
+                (false, true) => Ordering::Greater,
+                (false, false) => a.cmp(b),
+            },
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// Whether `s` is dot separated non-empty identifiers of alphanumerics and hyphens.
+fn is_valid_identifiers(s: &str) -> bool {
+    s.split('.')
+        .all(|identifier| !identifier.is_empty() && identifier.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'-'))
+}
+
+impl Ord for FirmwareVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.version()
+            .cmp(&other.version())
+            .then_with(|| compare_prerelease(&self.prerelease, &other.prerelease))
+            .then_with(|| self.product.cmp(&other.product))
+            .then_with(|| self.build.cmp(&other.build))
+    }
+}
+
+impl PartialOrd for FirmwareVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}.{}.{}", self.product, self.major, self.minor, self.patch)?;
+        if !self.prerelease.is_empty() {
+            write!(f, "-{}", self.prerelease)?;
+        }
+        if !self.build.is_empty() {
+            write!(f, "+{}", self.build)?;
+        }
+        Ok(())
+    }
+}
+
+/// Minimum firmware version required for a model.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MinimumFirmware {
+    pub kind: DeviceKind,
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+/// Result of checking the firmware of a device against a table of `MinimumFirmware`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FirmwareCheck {
+    UpToDate,
+    /// The firmware is older than the minimum version.
+    Outdated(MinimumFirmware),
+    /// The table has no entry for the model of the device.
+    NoPolicy,
+    /// The firmware version of the device cannot be parsed.
+    UnknownVersion,
+}
+
+impl FromStr for FirmwareVersion {
+    type Err = ModelNodeParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (product, version) = s.split_once('/').ok_or(ModelNodeParseError::FirmwareVersionParseError)?;
+        if product.is_empty() {
+            return Err(ModelNodeParseError::FirmwareVersionParseError);
+        }
+        let (version, build) = version.split_once('+').map_or((version, None), |(version, build)| (version, Some(build)));
+        let (version, prerelease) = version.split_once('-').map_or((version, None), |(version, prerelease)| (version, Some(prerelease)));
+        let suffix = |suffix: Option<&str>| match suffix {
+            None => Ok(String::new()),
+            Some(suffix) if is_valid_identifiers(suffix) => {
+                String::from_str(suffix).map_err(|_| ModelNodeParseError::FirmwareVersionParseError)
+            }
+            Some(_) => Err(ModelNodeParseError::FirmwareVersionParseError),
+        };
+        let (prerelease, build) = (suffix(prerelease)?, suffix(build)?);
+        let mut numbers = version.split('.').map(u16::from_str);
+        let mut next = || match numbers.next() {
+            Some(Ok(n)) => Ok(n),
+            _ => Err(ModelNodeParseError::FirmwareVersionParseError),
+        };
+        let (major, minor, patch) = (next()?, next()?, next()?);
+        if numbers.next().is_some() {
+            return Err(ModelNodeParseError::FirmwareVersionParseError);
+        }
+        Ok(Self {
+            product: String::from_str(product).map_err(|_| ModelNodeParseError::FirmwareVersionParseError)?,
+            major,
+            minor,
+            patch,
+            prerelease,
+            build,
+        })
+    }
+}
+
+impl<C: Capacities> Device<C> {
+    /// Parsed firmware version, or `None` if it is not in the known form.
+    pub fn firmware(&self) -> Option<FirmwareVersion> {
+        FirmwareVersion::from_str(&self.firmware_version).ok()
+    }
+
+    /// Hardware model detected from the firmware version, falling back to the serial number.
+    pub fn kind(&self) -> DeviceKind {
+        match self.firmware().map(|firmware| firmware.kind()) {
+            Some(kind) if kind != DeviceKind::Unknown => kind,
+            _ => DeviceKind::from_serial_number(&self.serial_number),
+        }
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.kind().capabilities()
+    }
+
+    /// Check the firmware against the minimum version of the model in `policy`.
+    /// The first entry of the model is used if the table has several. A prerelease of the minimum version is outdated.
+    pub fn check_firmware(&self, policy: &[MinimumFirmware]) -> FirmwareCheck {
+        let firmware = match self.firmware() {
+            Some(firmware) => firmware,
+            None => return FirmwareCheck::UnknownVersion,
+        };
+        let kind = self.kind();
+        match policy.iter().find(|minimum| minimum.kind == kind) {
+            None => FirmwareCheck::NoPolicy,
+            Some(minimum)
+                if (firmware.version(), firmware.prerelease.is_empty()) < ((minimum.major, minimum.minor, minimum.patch), true) =>
+            {
+                FirmwareCheck::Outdated(*minimum)
+            }
+            Some(_) => FirmwareCheck::UpToDate,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn device(firmware_version: &str, serial_number: &str) -> Device {
+        Device {
+            firmware_version: String::from(firmware_version),
+            serial_number: String::from(serial_number),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_firmware_version() {
+        let firmware = FirmwareVersion::from_str("Remo-E-lite/1.7.4").unwrap();
+        assert_eq!(firmware.product.as_str(), "Remo-E-lite");
+        assert_eq!((firmware.major, firmware.minor, firmware.patch), (1, 7, 4));
+        assert_eq!(firmware.kind(), DeviceKind::RemoELite);
+        let firmware = FirmwareVersion::from_str("Remo/1.10.0-beta+abcdef").unwrap();
+        assert_eq!((firmware.major, firmware.minor, firmware.patch), (1, 10, 0));
+        assert_eq!((firmware.prerelease.as_str(), firmware.build.as_str()), ("beta", "abcdef"));
+        let firmware = FirmwareVersion::from_str("Remo/1.10.0+build-1").unwrap();
+        assert_eq!((firmware.prerelease.as_str(), firmware.build.as_str()), ("", "build-1"));
+
+        assert!(FirmwareVersion::from_str("Remo").is_err());
+        assert!(FirmwareVersion::from_str("/1.0.0").is_err());
+        assert!(FirmwareVersion::from_str("Remo/1.0").is_err());
+        assert!(FirmwareVersion::from_str("Remo/1.0.0.0").is_err());
+        assert!(FirmwareVersion::from_str("Remo/1.x.0").is_err());
+        assert!(FirmwareVersion::from_str("Remo/1.0.0-").is_err());
+        assert!(FirmwareVersion::from_str("Remo/1.0.0+").is_err());
+        assert!(FirmwareVersion::from_str("Remo/1.0.0-rc..1").is_err());
+
+        // The product name longer than the storage is an error.
+        let mut long = String::<{ MAX_FIRMWARE_VERSION_LEN + 8 }>::new();
+        (0..MAX_FIRMWARE_VERSION_LEN + 1).for_each(|_| long.push('X').unwrap());
+        long.push_str("/1.2.3").unwrap();
+        assert_eq!(FirmwareVersion::from_str(&long), Err(ModelNodeParseError::FirmwareVersionParseError));
+    }
+
+    #[test]
+    fn test_firmware_version_order() {
+        let v = |s: &str| FirmwareVersion::from_str(s).unwrap();
+        assert!(v("Remo/1.9.9") < v("Remo/1.10.0"));
+        assert!(v("Remo/1.10.0") < v("Remo/2.0.0"));
+        assert!(v("Remo/1.10.1") > v("Remo/1.10.0"));
+        assert_eq!(v("Remo-mini/1.10.0").cmp(&v("Remo-mini/1.10.0")), Ordering::Equal);
+
+        // A prerelease is older than its release, and build metadata does not affect the precedence.
+        assert_ne!(v("Remo/1.7.4-rc1"), v("Remo/1.7.4"));
+        assert!(v("Remo/1.7.4-rc1") < v("Remo/1.7.4"));
+        assert!(v("Remo/1.7.4-rc1") > v("Remo/1.7.3"));
+        assert!(v("Remo/1.7.4+abc") > v("Remo/1.7.4-rc1+xyz"));
+        let ordered = [
+            "Remo/1.0.0-alpha",
+            "Remo/1.0.0-alpha.1",
+            "Remo/1.0.0-alpha.beta",
+            "Remo/1.0.0-beta",
+            "Remo/1.0.0-beta.2",
+            "Remo/1.0.0-beta.11",
+            "Remo/1.0.0-rc.1",
+            "Remo/1.0.0",
+        ];
+        assert!(ordered.windows(2).all(|pair| v(pair[0]) < v(pair[1])));
+
+        for input in ["Remo-E-lite/1.7.4-rc1", "Remo/1.10.0-beta+abcdef", "Remo/1.10.0+build-1", "Remo-mini/1.10.0"] {
+            let mut s = String::<MAX_FIRMWARE_VERSION_LEN>::new();
+            core::fmt::Write::write_fmt(&mut s, format_args!("{}", v(input))).unwrap();
+            assert_eq!(s.as_str(), input);
+        }
+    }
+
+    #[test]
+    fn test_check_firmware() {
+        const POLICY: &[MinimumFirmware] = &[
+            MinimumFirmware { kind: DeviceKind::Remo, major: 1, minor: 10, patch: 0 },
+            MinimumFirmware { kind: DeviceKind::RemoMini, major: 1, minor: 10, patch: 0 },
+        ];
+        assert_eq!(device("Remo/1.10.0", "1W012345678901").check_firmware(POLICY), FirmwareCheck::UpToDate);
+        assert_eq!(
+            device("Remo/1.9.9", "1W300000000000").check_firmware(POLICY),
+            FirmwareCheck::Outdated(POLICY[0])
+        );
+        assert_eq!(
+            device("Remo/1.10.0-rc1", "1W300000000000").check_firmware(POLICY),
+            FirmwareCheck::Outdated(POLICY[0])
+        );
+        assert_eq!(device("Remo/1.10.1-rc1", "1W300000000000").check_firmware(POLICY), FirmwareCheck::UpToDate);
+        assert_eq!(device("Remo-E-lite/1.7.4", "4W012345678901").check_firmware(POLICY), FirmwareCheck::NoPolicy);
+        assert_eq!(device("unknown", "2B012345678901").check_firmware(POLICY), FirmwareCheck::UnknownVersion);
+    }
+
+    #[test]
+    fn test_device_kind() {
+        let mini = device("Remo-mini/1.10.0", "2B012345678901");
+        assert_eq!(mini.kind(), DeviceKind::RemoMini);
+        assert_eq!(mini.capabilities(), Capabilities { temperature: true, ..Default::default() });
+        let remo = device("Remo/1.10.0", "1W012345678901");
+        assert_eq!(remo.kind(), DeviceKind::Remo);
+        assert!(remo.capabilities().motion);
+        assert!(!remo.capabilities().smart_meter);
+        let e_lite = device("Remo-E-lite/1.7.4", "4W012345678901");
+        assert_eq!(e_lite.kind(), DeviceKind::RemoELite);
+        assert!(e_lite.capabilities().smart_meter);
+
+        assert_eq!(device("Remo-3/1.14.0", "").kind(), DeviceKind::Remo3);
+        assert!(DeviceKind::Remo3.capabilities().humidity);
+        assert_eq!(device("Remo-E/1.7.4", "").capabilities(), Capabilities { smart_meter: true, ..Default::default() });
+        assert_eq!(device("Remo-nano/1.0.0", "").kind(), DeviceKind::RemoNano);
+        assert_eq!(DeviceKind::RemoNano.capabilities(), Capabilities::default());
+
+        // Falls back to the serial number.
+        assert_eq!(device("", "2B012345678901").kind(), DeviceKind::RemoMini);
+        assert_eq!(device("Remo-X/1.0.0", "9Z012345678901").kind(), DeviceKind::Unknown);
+        assert_eq!(DeviceKind::Unknown.capabilities(), Capabilities::default());
+    }
+}
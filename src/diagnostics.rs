@@ -0,0 +1,128 @@
+// Diagnostics of fields which failed to parse in lenient mode.
+// Copyright 2022-2023 Kenta Ida 
+// SPDX-License-Identifier: MIT
+//
+use core::cell::{Cell, RefCell};
+
+use heapless::Vec;
+
+use crate::common_types::ModelNodeParseError;
+use crate::node_key::ModelNodeKey;
+
+/// What happened to a field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// The field failed to parse and was replaced with its default value in lenient mode.
+    Invalid(ModelNodeParseError),
+    /// The string was truncated to fit its storage. `length` is the original length in bytes.
+    Truncated { length: usize },
+}
+
+/// A field which was not parsed as is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FieldDiagnostic {
+    /// Key of the field.
+    pub key: ModelNodeKey,
+    /// Index of the device or the appliance in the document.
+    pub index: usize,
+    pub kind: DiagnosticKind,
+}
+
+/// Receives the failures of fields in lenient mode and the truncated strings.
+/// See `ParserOptions::lenient` and `ParserOptions::with_diagnostics`.
+pub trait DiagnosticsSink {
+    fn report(&self, diagnostic: FieldDiagnostic);
+}
+
+/// Ignores all diagnostics.
+impl DiagnosticsSink for () {
+    fn report(&self, _diagnostic: FieldDiagnostic) {}
+}
+
+/// Keeps the first `N` entries reported to a sink and counts the rest.
+///
+/// The sink traits take `&self` as the sink is shared by reference while parsing,
+/// so the entries are kept in a `RefCell`.
+#[derive(Debug)]
+pub struct BoundedSink<T, const N: usize> {
+    entries: RefCell<Vec<T, N>>,
+    dropped: Cell<usize>,
+}
+
+impl<T, const N: usize> BoundedSink<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            entries: RefCell::new(Vec::new()),
+            dropped: Cell::new(0),
+        }
+    }
+
+    /// Number of the entries kept.
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    /// No entries are kept. Check `dropped` for the entries not kept.
+    pub fn is_empty(&self) -> bool {
+        self.entries.borrow().is_empty()
+    }
+
+    /// Number of the entries dropped as the storage is full.
+    pub fn dropped(&self) -> usize {
+        self.dropped.get()
+    }
+
+    /// Take the entries kept, and clear the dropped count.
+    pub fn take(&self) -> Vec<T, N> {
+        self.dropped.set(0);
+        self.entries.take()
+    }
+
+    /// Keep `entry`, or count it as dropped if the storage is full.
+    pub fn push(&self, entry: T) {
+        if self.entries.borrow_mut().push(entry).is_err() {
+            self.dropped.set(self.dropped.get() + 1);
+        }
+    }
+
+    /// Whether any entry kept satisfies `predicate`.
+    pub fn any(&self, predicate: impl FnMut(&T) -> bool) -> bool {
+        self.entries.borrow().iter().any(predicate)
+    }
+}
+
+impl<T, const N: usize> Default for BoundedSink<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Keeps the first `N` diagnostics and counts the rest.
+pub type Diagnostics<const N: usize> = BoundedSink<FieldDiagnostic, N>;
+
+impl<const N: usize> DiagnosticsSink for Diagnostics<N> {
+    fn report(&self, diagnostic: FieldDiagnostic) {
+        self.push(diagnostic);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dropped_diagnostics() {
+        let diagnostic = FieldDiagnostic {
+            key: ModelNodeKey::Name,
+            index: 0,
+            kind: DiagnosticKind::Invalid(ModelNodeParseError::StringTooLong),
+        };
+        let diagnostics = Diagnostics::<0>::new();
+        diagnostics.report(diagnostic);
+        assert_eq!((diagnostics.len(), diagnostics.is_empty(), diagnostics.dropped()), (0, true, 1));
+        let diagnostics = Diagnostics::<1>::new();
+        diagnostics.report(diagnostic);
+        diagnostics.report(diagnostic);
+        assert_eq!((diagnostics.len(), diagnostics.is_empty(), diagnostics.dropped()), (1, false, 1));
+    }
+}
@@ -0,0 +1,456 @@
+// ECHONET Lite property decoders for Remo Cloud API.
+// Copyright 2022-2023 Kenta Ida 
+// SPDX-License-Identifier: MIT
+//
+use core::str::FromStr;
+
+use crate::appliances::{ApplianceType, EchonetLiteProperty};
+use crate::common_types::*;
+use crate::config::Capacities;
+
+/// Decodes a sequence of `EchonetLiteProperty` into typed fields.
+pub trait EchonetLiteDecoder {
+    /// Update the decoded fields with the property.
+    /// Returns `Ok(true)` if the EPC is known by the decoder, `Ok(false)` if the property is ignored.
+    fn update<C: Capacities>(&mut self, property: &EchonetLiteProperty<C>) -> Result<bool, ModelNodeParseError>;
+}
+
+/// Parse the value of the property. The Cloud API returns property values as decimal strings.
+fn parse_value<T: FromStr, C: Capacities>(property: &EchonetLiteProperty<C>) -> Result<T, ModelNodeParseError> {
+    T::from_str(&property.val).map_err(|_| ModelNodeParseError::InvalidPropertyValue)
+}
+
+fn update_timestamp<C: Capacities>(updated_at: &mut Timestamp, property: &EchonetLiteProperty<C>) {
+    if *updated_at < property.updated_at {
+        *updated_at = property.updated_at;
+    }
+}
+
+/// Unit of cumulative electric energy (EPC 0xE1 of the smart meter class)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnergyUnit {
+    KWh1,
+    KWh0_1,
+    KWh0_01,
+    KWh0_001,
+    KWh0_0001,
+    KWh10,
+    KWh100,
+    KWh1000,
+    KWh10000,
+}
+
+impl EnergyUnit {
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0x00 => Some(Self::KWh1),
+            0x01 => Some(Self::KWh0_1),
+            0x02 => Some(Self::KWh0_01),
+            0x03 => Some(Self::KWh0_001),
+            0x04 => Some(Self::KWh0_0001),
+            0x0A => Some(Self::KWh10),
+            0x0B => Some(Self::KWh100),
+            0x0C => Some(Self::KWh1000),
+            0x0D => Some(Self::KWh10000),
+            _ => None,
+        }
+    }
+    /// Energy represented by one count of this unit in milliwatt-hours.
+    pub fn milliwatt_hours(&self) -> u64 {
+        match self {
+            Self::KWh1 => 1_000_000,
+            Self::KWh0_1 => 100_000,
+            Self::KWh0_01 => 10_000,
+            Self::KWh0_001 => 1_000,
+            Self::KWh0_0001 => 100,
+            Self::KWh10 => 10_000_000,
+            Self::KWh100 => 100_000_000,
+            Self::KWh1000 => 1_000_000_000,
+            Self::KWh10000 => 10_000_000_000,
+        }
+    }
+}
+
+/// Operation state of a storage battery (EPC 0xCF) or an EV charger/discharger (EPC 0xDA).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChargingState {
+    RapidCharging,
+    Charging,
+    Discharging,
+    Standby,
+    Auto,
+    Other(u8),
+}
+
+impl From<u8> for ChargingState {
+    fn from(code: u8) -> Self {
+        match code {
+            0x41 => Self::RapidCharging,
+            0x42 => Self::Charging,
+            0x43 => Self::Discharging,
+            0x44 => Self::Standby,
+            0x46 => Self::Auto,
+            code => Self::Other(code),
+        }
+    }
+}
+
+/// Vehicle connection and chargeable/dischargeable status of an EV charger/discharger (EPC 0xC7).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VehicleConnectionState {
+    NotConnected,
+    Connected,
+    Chargeable,
+    Dischargeable,
+    ChargeableAndDischargeable,
+    Other(u8),
+}
+
+impl From<u8> for VehicleConnectionState {
+    fn from(code: u8) -> Self {
+        match code {
+            0x30 => Self::NotConnected,
+            0x40 => Self::Connected,
+            0x41 => Self::Chargeable,
+            0x42 => Self::Dischargeable,
+            0x43 => Self::ChargeableAndDischargeable,
+            code => Self::Other(code),
+        }
+    }
+}
+
+/// Properties of low voltage smart electric energy meter class (0x0288).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SmartMeterProperties {
+    /// Coefficient (0xD3)
+    pub coefficient: Option<u32>,
+    /// Number of effective digits for cumulative amounts of electric energy (0xD7)
+    pub effective_digits: Option<u8>,
+    /// Measured cumulative amount of electric energy in normal direction (0xE0)
+    pub normal_direction_cumulative_energy: Option<u32>,
+    /// Unit for cumulative amounts of electric energy (0xE1)
+    pub cumulative_energy_unit: Option<EnergyUnit>,
+    /// Measured cumulative amount of electric energy in reverse direction (0xE3)
+    pub reverse_direction_cumulative_energy: Option<u32>,
+    /// Measured instantaneous electric power in watts (0xE7)
+    pub instantaneous_power: Option<i32>,
+    pub updated_at: Timestamp,
+}
+
+impl SmartMeterProperties {
+    /// `None` if the value or the unit is unknown, or the energy overflows `u64`.
+    fn to_milliwatt_hours(&self, value: Option<u32>) -> Option<u64> {
+        let unit = self.cumulative_energy_unit?.milliwatt_hours();
+        let coefficient = self.coefficient.unwrap_or(1) as u64;
+        (value? as u64).checked_mul(coefficient)?.checked_mul(unit)
+    }
+    /// Cumulative amount of electric energy in normal direction in milliwatt-hours.
+    pub fn normal_direction_cumulative_energy_mwh(&self) -> Option<u64> {
+        self.to_milliwatt_hours(self.normal_direction_cumulative_energy)
+    }
+    /// Cumulative amount of electric energy in reverse direction in milliwatt-hours.
+    pub fn reverse_direction_cumulative_energy_mwh(&self) -> Option<u64> {
+        self.to_milliwatt_hours(self.reverse_direction_cumulative_energy)
+    }
+    /// Energy at which the cumulative counters wrap around in milliwatt-hours.
+    pub fn cumulative_energy_modulus_mwh(&self) -> Option<u64> {
+        let modulus = 10u64.checked_pow(self.effective_digits? as u32)?;
+        let unit = self.cumulative_energy_unit?.milliwatt_hours();
+        let coefficient = self.coefficient.unwrap_or(1) as u64;
+        modulus.checked_mul(coefficient)?.checked_mul(unit)
+    }
+}
+
+impl EchonetLiteDecoder for SmartMeterProperties {
+    fn update<C: Capacities>(&mut self, property: &EchonetLiteProperty<C>) -> Result<bool, ModelNodeParseError> {
+        match property.epc {
+            0xD3 => self.coefficient = Some(parse_value(property)?),
+            0xD7 => self.effective_digits = Some(parse_value(property)?),
+            0xE0 => self.normal_direction_cumulative_energy = Some(parse_value(property)?),
+            0xE1 => {
+                self.cumulative_energy_unit = Some(
+                    EnergyUnit::from_code(parse_value(property)?)
+                        .ok_or(ModelNodeParseError::UnexpectedEnumValue)?,
+                )
+            }
+            0xE3 => self.reverse_direction_cumulative_energy = Some(parse_value(property)?),
+            0xE7 => self.instantaneous_power = Some(parse_value(property)?),
+            _ => return Ok(false),
+        }
+        update_timestamp(&mut self.updated_at, property);
+        Ok(true)
+    }
+}
+
+/// Properties of storage battery class (0x027D).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StorageBatteryProperties {
+    /// Working operation status (0xCF)
+    pub working_operation_state: Option<ChargingState>,
+    /// Measured instantaneous charging/discharging electric power in watts (0xD3).
+    /// Positive while charging, negative while discharging.
+    pub charge_discharge_power: Option<i32>,
+    /// Remaining stored electricity in watt-hours (0xE2)
+    pub remaining_capacity_wh: Option<u32>,
+    /// Remaining stored electricity in percent, a.k.a. state of charge (0xE4)
+    pub remaining_capacity_percent: Option<u8>,
+    pub updated_at: Timestamp,
+}
+
+impl EchonetLiteDecoder for StorageBatteryProperties {
+    fn update<C: Capacities>(&mut self, property: &EchonetLiteProperty<C>) -> Result<bool, ModelNodeParseError> {
+        match property.epc {
+            0xCF => self.working_operation_state = Some(ChargingState::from(parse_value::<u8, C>(property)?)),
+            0xD3 => self.charge_discharge_power = Some(parse_value(property)?),
+            0xE2 => self.remaining_capacity_wh = Some(parse_value(property)?),
+            0xE4 => self.remaining_capacity_percent = Some(parse_value(property)?),
+            _ => return Ok(false),
+        }
+        update_timestamp(&mut self.updated_at, property);
+        Ok(true)
+    }
+}
+
+/// Properties of household solar power generation class (0x0279).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SolarPowerProperties {
+    /// Measured instantaneous amount of electricity generated in watts (0xE0)
+    pub instantaneous_generation_power: Option<u32>,
+    /// Measured cumulative amount of electric energy generated in watt-hours (0xE1)
+    pub cumulative_generated_energy_wh: Option<u32>,
+    /// Measured cumulative amount of electric energy sold in watt-hours (0xE3)
+    pub cumulative_sold_energy_wh: Option<u32>,
+    pub updated_at: Timestamp,
+}
+
+impl EchonetLiteDecoder for SolarPowerProperties {
+    fn update<C: Capacities>(&mut self, property: &EchonetLiteProperty<C>) -> Result<bool, ModelNodeParseError> {
+        match property.epc {
+            0xE0 => self.instantaneous_generation_power = Some(parse_value(property)?),
+            0xE1 => self.cumulative_generated_energy_wh = Some(parse_value(property)?),
+            0xE3 => self.cumulative_sold_energy_wh = Some(parse_value(property)?),
+            _ => return Ok(false),
+        }
+        update_timestamp(&mut self.updated_at, property);
+        Ok(true)
+    }
+}
+
+/// Properties of electric water heater class (0x026B).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ElectricWaterHeaterProperties {
+    /// Water heating status (0xB2). `true` while heating.
+    pub heating: Option<bool>,
+    /// Measured amount of water remaining in tank in liters (0xE1)
+    pub remaining_water_liters: Option<u16>,
+    /// Tank capacity in liters (0xE2)
+    pub tank_capacity_liters: Option<u16>,
+    pub updated_at: Timestamp,
+}
+
+impl ElectricWaterHeaterProperties {
+    /// Remaining water in the tank in percent of the tank capacity.
+    pub fn tank_level_percent(&self) -> Option<u8> {
+        let remaining = self.remaining_water_liters? as u32;
+        let capacity = self.tank_capacity_liters? as u32;
+        if capacity == 0 {
+            return None;
+        }
+        Some((remaining * 100 / capacity).min(100) as u8)
+    }
+}
+
+impl EchonetLiteDecoder for ElectricWaterHeaterProperties {
+    fn update<C: Capacities>(&mut self, property: &EchonetLiteProperty<C>) -> Result<bool, ModelNodeParseError> {
+        match property.epc {
+            0xB2 => {
+                self.heating = match parse_value::<u8, C>(property)? {
+                    0x41 => Some(true),
+                    0x42 => Some(false),
+                    _ => return Err(ModelNodeParseError::UnexpectedEnumValue),
+                }
+            }
+            0xE1 => self.remaining_water_liters = Some(parse_value(property)?),
+            0xE2 => self.tank_capacity_liters = Some(parse_value(property)?),
+            _ => return Ok(false),
+        }
+        update_timestamp(&mut self.updated_at, property);
+        Ok(true)
+    }
+}
+
+/// Properties of electric vehicle charger/discharger class (0x027E).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EVCDProperties {
+    /// Vehicle connection and chargeable/dischargeable status (0xC7)
+    pub vehicle_connection: Option<VehicleConnectionState>,
+    /// Measured instantaneous charging/discharging electric power in watts (0xD3).
+    /// Positive while charging, negative while discharging.
+    pub charge_discharge_power: Option<i32>,
+    /// Operation mode (0xDA)
+    pub operation_mode: Option<ChargingState>,
+    /// Remaining battery capacity of the vehicle in watt-hours (0xE2)
+    pub remaining_capacity_wh: Option<u32>,
+    /// Remaining battery capacity of the vehicle in percent (0xE4)
+    pub remaining_capacity_percent: Option<u8>,
+    pub updated_at: Timestamp,
+}
+
+impl EchonetLiteDecoder for EVCDProperties {
+    fn update<C: Capacities>(&mut self, property: &EchonetLiteProperty<C>) -> Result<bool, ModelNodeParseError> {
+        match property.epc {
+            0xC7 => self.vehicle_connection = Some(VehicleConnectionState::from(parse_value::<u8, C>(property)?)),
+            0xD3 => self.charge_discharge_power = Some(parse_value(property)?),
+            0xDA => self.operation_mode = Some(ChargingState::from(parse_value::<u8, C>(property)?)),
+            0xE2 => self.remaining_capacity_wh = Some(parse_value(property)?),
+            0xE4 => self.remaining_capacity_percent = Some(parse_value(property)?),
+            _ => return Ok(false),
+        }
+        update_timestamp(&mut self.updated_at, property);
+        Ok(true)
+    }
+}
+
+/// Decoded properties of an ECHONET Lite appliance.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EchonetLiteProperties {
+    SmartMeter(SmartMeterProperties),
+    StorageBattery(StorageBatteryProperties),
+    SolarPower(SolarPowerProperties),
+    ElectricWaterHeater(ElectricWaterHeaterProperties),
+    EVCD(EVCDProperties),
+}
+
+impl EchonetLiteProperties {
+    /// Create an empty decoder for the appliance type. Returns `None` if the appliance type has no decoder.
+    pub fn new(type_: &ApplianceType) -> Option<Self> {
+        match type_ {
+            ApplianceType::SmartMeter => Some(Self::SmartMeter(Default::default())),
+            ApplianceType::StorageBattery => Some(Self::StorageBattery(Default::default())),
+            ApplianceType::SolarPower => Some(Self::SolarPower(Default::default())),
+            ApplianceType::ElectricWaterHeater => Some(Self::ElectricWaterHeater(Default::default())),
+            ApplianceType::EVCD => Some(Self::EVCD(Default::default())),
+            _ => None,
+        }
+    }
+}
+
+impl EchonetLiteDecoder for EchonetLiteProperties {
+    fn update<C: Capacities>(&mut self, property: &EchonetLiteProperty<C>) -> Result<bool, ModelNodeParseError> {
+        match self {
+            Self::SmartMeter(properties) => properties.update(property),
+            Self::StorageBattery(properties) => properties.update(property),
+            Self::SolarPower(properties) => properties.update(property),
+            Self::ElectricWaterHeater(properties) => properties.update(property),
+            Self::EVCD(properties) => properties.update(property),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use fuga_json_seq_parser::BufferReader;
+    use heapless::String;
+
+    use super::*;
+    use crate::appliances::{read_appliances, ApplianceSubNode};
+    use crate::parser_options::ParserOptions;
+
+    fn property(epc: u32, val: &str) -> EchonetLiteProperty {
+        EchonetLiteProperty {
+            name: String::new(),
+            epc,
+            val: String::from(val),
+            updated_at: Timestamp::from_str("2022-10-22T11:38:14Z").unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_decode_smart_meter() {
+        let input = include_str!("../data/appliances.json");
+        let mut reader = BufferReader::new(input.as_bytes());
+        let mut properties = EchonetLiteProperties::new(&ApplianceType::SmartMeter).unwrap();
+        read_appliances(&mut reader, Some(input.len()), &ParserOptions::default(), |_appliance, sub_node| {
+            if let Some(ApplianceSubNode::EchonetLiteProperty(property)) = sub_node {
+                assert!(properties.update(property).unwrap());
+            }
+        })
+        .unwrap();
+        let properties = match properties {
+            EchonetLiteProperties::SmartMeter(properties) => properties,
+            _ => panic!("unexpected decoder type"),
+        };
+        assert_eq!(properties.effective_digits, Some(7));
+        assert_eq!(properties.normal_direction_cumulative_energy, Some(1097158));
+        assert_eq!(properties.cumulative_energy_unit, Some(EnergyUnit::KWh0_01));
+        assert_eq!(properties.instantaneous_power, Some(397));
+        assert_eq!(properties.normal_direction_cumulative_energy_mwh(), Some(10_971_580_000));
+        assert_eq!(properties.cumulative_energy_modulus_mwh(), Some(100_000_000_000));
+        assert_eq!(properties.updated_at, Timestamp::from_str("2022-10-22T11:38:14Z").unwrap());
+    }
+
+    #[test]
+    fn test_smart_meter_energy_overflow() {
+        // 8 digits with the maximum coefficient in units of 10000 kWh do not fit in u64.
+        let properties = SmartMeterProperties {
+            coefficient: Some(999_999),
+            normal_direction_cumulative_energy: Some(99_999_999),
+            cumulative_energy_unit: Some(EnergyUnit::KWh10000),
+            reverse_direction_cumulative_energy: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(properties.normal_direction_cumulative_energy_mwh(), None);
+        assert_eq!(properties.reverse_direction_cumulative_energy_mwh(), Some(9_999_990_000_000_000));
+    }
+
+    #[test]
+    fn test_decode_storage_battery() {
+        let mut properties = StorageBatteryProperties::default();
+        assert!(properties.update(&property(0xCF, "67")).unwrap());
+        assert!(properties.update(&property(0xD3, "-1200")).unwrap());
+        assert!(properties.update(&property(0xE4, "83")).unwrap());
+        assert!(!properties.update(&property(0x80, "48")).unwrap());
+        assert_eq!(properties.working_operation_state, Some(ChargingState::Discharging));
+        assert_eq!(properties.charge_discharge_power, Some(-1200));
+        assert_eq!(properties.remaining_capacity_percent, Some(83));
+    }
+
+    #[test]
+    fn test_decode_solar_power() {
+        let mut properties = SolarPowerProperties::default();
+        assert!(properties.update(&property(0xE0, "2450")).unwrap());
+        assert!(properties.update(&property(0xE1, "123456")).unwrap());
+        assert_eq!(properties.instantaneous_generation_power, Some(2450));
+        assert_eq!(properties.cumulative_generated_energy_wh, Some(123456));
+    }
+
+    #[test]
+    fn test_decode_electric_water_heater() {
+        let mut properties = ElectricWaterHeaterProperties::default();
+        assert!(properties.update(&property(0xB2, "65")).unwrap());
+        assert!(properties.update(&property(0xE1, "222")).unwrap());
+        assert!(properties.update(&property(0xE2, "370")).unwrap());
+        assert_eq!(properties.heating, Some(true));
+        assert_eq!(properties.tank_level_percent(), Some(60));
+    }
+
+    #[test]
+    fn test_decode_evcd() {
+        let mut properties = EVCDProperties::default();
+        assert!(properties.update(&property(0xC7, "65")).unwrap());
+        assert!(properties.update(&property(0xDA, "66")).unwrap());
+        assert!(properties.update(&property(0xD3, "3000")).unwrap());
+        assert_eq!(properties.vehicle_connection, Some(VehicleConnectionState::Chargeable));
+        assert_eq!(properties.operation_mode, Some(ChargingState::Charging));
+        assert_eq!(properties.charge_discharge_power, Some(3000));
+    }
+
+    #[test]
+    fn test_decode_invalid_value() {
+        let mut properties = SolarPowerProperties::default();
+        assert!(matches!(
+            properties.update(&property(0xE0, "abc")),
+            Err(ModelNodeParseError::InvalidPropertyValue)
+        ));
+    }
+}
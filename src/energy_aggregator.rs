@@ -0,0 +1,335 @@
+// Power/energy time-series aggregator for smart meter readings.
+// Copyright 2022-2023 Kenta Ida 
+// SPDX-License-Identifier: MIT
+//
+use chrono::{Duration, TimeZone, Utc};
+use heapless::Deque;
+
+use crate::common_types::*;
+use crate::echonet_lite::SmartMeterProperties;
+
+pub(crate) const SECONDS_PER_HOUR: i64 = 60 * 60;
+const SECONDS_PER_DAY: i64 = 24 * SECONDS_PER_HOUR;
+
+/// A reading of the smart meter at a point of time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SmartMeterReading {
+    pub updated_at: Timestamp,
+    /// Cumulative amount of electric energy in normal direction in milliwatt-hours.
+    pub cumulative_energy_mwh: u64,
+    /// Measured instantaneous electric power in watts.
+    pub instantaneous_power: Option<i32>,
+    /// Energy at which the cumulative counter wraps around in milliwatt-hours, if known.
+    pub modulus_mwh: Option<u64>,
+}
+
+impl SmartMeterProperties {
+    /// Take a reading from the decoded properties. Returns `None` if the cumulative energy is not known yet.
+    pub fn reading(&self) -> Option<SmartMeterReading> {
+        Some(SmartMeterReading {
+            updated_at: self.updated_at,
+            cumulative_energy_mwh: self.normal_direction_cumulative_energy_mwh()?,
+            instantaneous_power: self.instantaneous_power,
+            modulus_mwh: self.cumulative_energy_modulus_mwh(),
+        })
+    }
+}
+
+/// Energy consumption within a period.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EnergyBucket {
+    /// Start of the period.
+    pub start: Timestamp,
+    /// Energy consumed within the period in milliwatt-hours.
+    pub energy_mwh: u64,
+    /// Peak instantaneous power sampled within the period in watts.
+    pub peak_power: Option<i32>,
+    /// Number of readings sampled within the period.
+    pub samples: u32,
+    /// The energy contains a portion interpolated over a gap between readings, or the counter was reset.
+    pub has_gap: bool,
+}
+
+impl EnergyBucket {
+    fn new(start: Timestamp) -> Self {
+        Self {
+            start,
+            energy_mwh: 0,
+            peak_power: None,
+            samples: 0,
+            has_gap: false,
+        }
+    }
+    /// Energy consumed within the period in kilowatt-hours.
+    pub fn energy_kwh(&self) -> f32 {
+        self.energy_mwh as f32 / 1_000_000.0
+    }
+}
+
+/// Result of feeding a reading to `EnergyAggregator`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleResult {
+    /// The first reading. Energy is accumulated from the next reading.
+    First,
+    /// The reading is accumulated.
+    Accepted,
+    /// The reading is accumulated, but the interval from the previous reading exceeds the maximum gap.
+    Gap,
+    /// The reading has the same `updated_at` as the previous one and is ignored.
+    Duplicate,
+    /// The reading is older than the previous one and is ignored.
+    OutOfOrder,
+    /// The cumulative counter decreased without wrapping around. The energy since the previous reading is lost.
+    CounterReset,
+}
+
+/// Aggregates smart meter readings into rolling hourly and daily buckets.
+/// `HOURS` and `DAYS` are the number of buckets retained.
+pub struct EnergyAggregator<const HOURS: usize, const DAYS: usize> {
+    utc_offset_seconds: i64,
+    max_gap: Duration,
+    last: Option<SmartMeterReading>,
+    hourly: Deque<EnergyBucket, HOURS>,
+    daily: Deque<EnergyBucket, DAYS>,
+}
+
+pub(crate) fn period_start(t: i64, period: i64, utc_offset_seconds: i64) -> i64 {
+    (t + utc_offset_seconds).div_euclid(period) * period - utc_offset_seconds
+}
+
+fn to_timestamp(t: i64) -> Timestamp {
+    Utc.timestamp_opt(t, 0).single().unwrap_or_default()
+}
+
+/// Get the bucket starts at `start`. A new bucket is appended if `start` is newer than any bucket.
+fn bucket_mut<const N: usize>(buckets: &mut Deque<EnergyBucket, N>, start: i64) -> Option<&mut EnergyBucket> {
+    let start = to_timestamp(start);
+    let is_newest = buckets.back().map(|b| b.start < start).unwrap_or(true);
+    if is_newest {
+        if buckets.is_full() {
+            buckets.pop_front();
+        }
+        buckets.push_back(EnergyBucket::new(start)).ok()?;
+        return buckets.back_mut();
+    }
+    buckets.iter_mut().find(|b| b.start == start)
+}
+
+/// Split the energy consumed between `from` and `to` at period boundaries in proportion to time.
+/// `f` is called with the start of each period and the energy within it.
+pub(crate) fn split_by_period<F: FnMut(i64, u64)>(
+    period: i64,
+    utc_offset_seconds: i64,
+    from: i64,
+    to: i64,
+    energy_mwh: u64,
+    mut f: F,
+) {
+    let total = (to - from) as u128;
+    let mut segment_start = from;
+    let mut distributed = 0u64;
+    while segment_start < to {
+        let bucket_start = period_start(segment_start, period, utc_offset_seconds);
+        let segment_end = (bucket_start + period).min(to);
+        let accumulated = (energy_mwh as u128 * (segment_end - from) as u128 / total) as u64;
+        f(bucket_start, accumulated - distributed);
+        distributed = accumulated;
+        segment_start = segment_end;
+    }
+}
+
+/// Distribute the energy consumed between `from` and `to` across the buckets in proportion to time.
+fn distribute<const N: usize>(
+    buckets: &mut Deque<EnergyBucket, N>,
+    period: i64,
+    utc_offset_seconds: i64,
+    from: i64,
+    to: i64,
+    energy_mwh: u64,
+    has_gap: bool,
+) {
+    split_by_period(period, utc_offset_seconds, from, to, energy_mwh, |start, energy_mwh| {
+        if let Some(bucket) = bucket_mut(buckets, start) {
+            bucket.energy_mwh += energy_mwh;
+            bucket.has_gap |= has_gap;
+        }
+    });
+}
+
+/// Energy consumed since the last reading.
+/// Returns the reason as `Err` if the energy cannot be determined from the readings.
+pub(crate) fn energy_since(last: &SmartMeterReading, reading: &SmartMeterReading) -> Result<u64, SampleResult> {
+    if reading.updated_at == last.updated_at {
+        return Err(SampleResult::Duplicate);
+    }
+    if reading.updated_at < last.updated_at {
+        return Err(SampleResult::OutOfOrder);
+    }
+    if reading.cumulative_energy_mwh >= last.cumulative_energy_mwh {
+        return Ok(reading.cumulative_energy_mwh - last.cumulative_energy_mwh);
+    }
+    reading
+        .modulus_mwh
+        .filter(|m| *m > last.cumulative_energy_mwh)
+        .map(|m| m - last.cumulative_energy_mwh + reading.cumulative_energy_mwh)
+        // Treat as wrap around only if the counter advanced less than a half of its range.
+        .filter(|energy_mwh| *energy_mwh < reading.modulus_mwh.unwrap_or(0) / 2)
+        .ok_or(SampleResult::CounterReset)
+}
+
+fn sample<const N: usize>(
+    buckets: &mut Deque<EnergyBucket, N>,
+    period: i64,
+    utc_offset_seconds: i64,
+    reading: &SmartMeterReading,
+    has_gap: bool,
+) {
+    let start = period_start(reading.updated_at.timestamp(), period, utc_offset_seconds);
+    if let Some(bucket) = bucket_mut(buckets, start) {
+        bucket.samples += 1;
+        bucket.has_gap |= has_gap;
+        if let Some(power) = reading.instantaneous_power {
+            bucket.peak_power = Some(bucket.peak_power.map_or(power, |peak| peak.max(power)));
+        }
+    }
+}
+
+impl<const HOURS: usize, const DAYS: usize> EnergyAggregator<HOURS, DAYS> {
+    /// Create an aggregator. Periods are aligned to the local time at `utc_offset_seconds` (e.g. `9 * 3600` for JST).
+    /// Intervals between readings longer than `max_gap` are flagged as gaps.
+    pub fn new(utc_offset_seconds: i32, max_gap: Duration) -> Self {
+        Self {
+            utc_offset_seconds: utc_offset_seconds as i64,
+            max_gap,
+            last: None,
+            hourly: Deque::new(),
+            daily: Deque::new(),
+        }
+    }
+
+    /// Feed a reading.
+    pub fn push(&mut self, reading: &SmartMeterReading) -> SampleResult {
+        let last = match self.last {
+            None => {
+                self.sample(reading, false);
+                self.last = Some(*reading);
+                return SampleResult::First;
+            }
+            Some(last) => last,
+        };
+        let energy_mwh = match energy_since(&last, reading) {
+            Ok(energy_mwh) => energy_mwh,
+            Err(SampleResult::CounterReset) => {
+                self.last = Some(*reading);
+                self.sample(reading, true);
+                return SampleResult::CounterReset;
+            }
+            Err(result) => return result,
+        };
+        self.last = Some(*reading);
+
+        let is_gap = reading.updated_at - last.updated_at > self.max_gap;
+        let from = last.updated_at.timestamp();
+        let to = reading.updated_at.timestamp();
+        if to > from {
+            distribute(&mut self.hourly, SECONDS_PER_HOUR, self.utc_offset_seconds, from, to, energy_mwh, is_gap);
+            distribute(&mut self.daily, SECONDS_PER_DAY, self.utc_offset_seconds, from, to, energy_mwh, is_gap);
+        }
+        self.sample(reading, is_gap);
+        if is_gap {
+            SampleResult::Gap
+        } else {
+            SampleResult::Accepted
+        }
+    }
+
+    fn sample(&mut self, reading: &SmartMeterReading, has_gap: bool) {
+        sample(&mut self.hourly, SECONDS_PER_HOUR, self.utc_offset_seconds, reading, has_gap);
+        sample(&mut self.daily, SECONDS_PER_DAY, self.utc_offset_seconds, reading, has_gap);
+    }
+
+    /// The last accepted reading.
+    pub fn last_reading(&self) -> Option<&SmartMeterReading> {
+        self.last.as_ref()
+    }
+    /// Hourly buckets from the oldest to the newest.
+    pub fn hourly(&self) -> impl Iterator<Item = &EnergyBucket> {
+        self.hourly.iter()
+    }
+    /// Daily buckets from the oldest to the newest.
+    pub fn daily(&self) -> impl Iterator<Item = &EnergyBucket> {
+        self.daily.iter()
+    }
+    /// The hourly bucket which contains `t`.
+    pub fn hour_of(&self, t: Timestamp) -> Option<&EnergyBucket> {
+        let start = to_timestamp(period_start(t.timestamp(), SECONDS_PER_HOUR, self.utc_offset_seconds));
+        self.hourly.iter().find(|b| b.start == start)
+    }
+    /// The daily bucket which contains `t`.
+    pub fn day_of(&self, t: Timestamp) -> Option<&EnergyBucket> {
+        let start = to_timestamp(period_start(t.timestamp(), SECONDS_PER_DAY, self.utc_offset_seconds));
+        self.daily.iter().find(|b| b.start == start)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::str::FromStr;
+
+    use super::*;
+
+    const JST: i32 = 9 * 3600;
+
+    fn reading(t: &str, cumulative_energy_mwh: u64, power: i32) -> SmartMeterReading {
+        SmartMeterReading {
+            updated_at: Timestamp::from_str(t).unwrap(),
+            cumulative_energy_mwh,
+            instantaneous_power: Some(power),
+            modulus_mwh: Some(100_000_000_000),
+        }
+    }
+
+    #[test]
+    fn test_aggregate_hourly() {
+        let mut aggregator = EnergyAggregator::<4, 2>::new(JST, Duration::minutes(5));
+        assert_eq!(aggregator.push(&reading("2022-10-22T11:58:00Z", 1_000_000, 300)), SampleResult::First);
+        assert_eq!(aggregator.push(&reading("2022-10-22T11:59:00Z", 1_010_000, 500)), SampleResult::Accepted);
+        assert_eq!(aggregator.push(&reading("2022-10-22T11:59:00Z", 1_010_000, 500)), SampleResult::Duplicate);
+        // Crossing the hour boundary: 20 Wh over 2 minutes is split evenly.
+        assert_eq!(aggregator.push(&reading("2022-10-22T12:01:00Z", 1_030_000, 400)), SampleResult::Accepted);
+        assert_eq!(aggregator.push(&reading("2022-10-22T12:00:00Z", 1_020_000, 400)), SampleResult::OutOfOrder);
+
+        let hours: heapless::Vec<_, 4> = aggregator.hourly().copied().collect();
+        assert_eq!(hours.len(), 2);
+        assert_eq!(hours[0].start, Timestamp::from_str("2022-10-22T11:00:00Z").unwrap());
+        assert_eq!(hours[0].energy_mwh, 20_000);
+        assert_eq!(hours[0].peak_power, Some(500));
+        assert_eq!(hours[0].samples, 2);
+        assert_eq!(hours[1].energy_mwh, 10_000);
+        assert_eq!(hours[1].peak_power, Some(400));
+
+        // The day starts at 15:00 UTC in JST.
+        let day = aggregator.day_of(Timestamp::from_str("2022-10-22T12:00:00Z").unwrap()).unwrap();
+        assert_eq!(day.start, Timestamp::from_str("2022-10-21T15:00:00Z").unwrap());
+        assert_eq!(day.energy_mwh, 30_000);
+        assert_eq!(day.samples, 3);
+    }
+
+    #[test]
+    fn test_aggregate_gap_and_wrap() {
+        let mut aggregator = EnergyAggregator::<4, 2>::new(JST, Duration::minutes(5));
+        aggregator.push(&reading("2022-10-22T10:30:00Z", 99_999_990_000, 300));
+        // The counter wraps around, 3 hours elapsed.
+        assert_eq!(aggregator.push(&reading("2022-10-22T13:30:00Z", 20_000, 300)), SampleResult::Gap);
+        let hours: heapless::Vec<_, 4> = aggregator.hourly().copied().collect();
+        assert_eq!(hours.len(), 4);
+        assert!(hours.iter().all(|b| b.has_gap));
+        assert_eq!(hours.iter().map(|b| b.energy_mwh).sum::<u64>(), 30_000);
+        assert_eq!(hours[0].energy_mwh, 5_000);
+        assert_eq!(hours[1].energy_mwh, 10_000);
+
+        // The counter was reset.
+        assert_eq!(aggregator.push(&reading("2022-10-22T13:31:00Z", 0, 300)), SampleResult::CounterReset);
+        assert_eq!(aggregator.last_reading().unwrap().cumulative_energy_mwh, 0);
+    }
+}
@@ -0,0 +1,310 @@
+// Registry of ECHONET Lite properties returned by Remo Cloud API.
+// Copyright 2022-2023 Kenta Ida 
+// SPDX-License-Identifier: MIT
+//
+use core::fmt::{self, Display};
+
+use crate::appliances::{ApplianceType, EchonetLiteProperty};
+use crate::common_types::*;
+use crate::config::Capacities;
+
+/// Unit of an ECHONET Lite property value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PropertyUnit {
+    None,
+    Watt,
+    WattHour,
+    KiloWattHour,
+    Ampere,
+    Percent,
+    Liter,
+}
+
+impl PropertyUnit {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::None => "",
+            Self::Watt => "W",
+            Self::WattHour => "Wh",
+            Self::KiloWattHour => "kWh",
+            Self::Ampere => "A",
+            Self::Percent => "%",
+            Self::Liter => "L",
+        }
+    }
+}
+
+/// Encoding of `EchonetLiteProperty::val`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PropertyEncoding {
+    /// Unsigned decimal number.
+    Decimal,
+    /// Signed decimal number.
+    Signed,
+    /// Raw bytes in hexadecimal.
+    Hex,
+    /// Decimal code with the list of known (code, name) pairs.
+    Enum(&'static [(u32, &'static str)]),
+}
+
+/// Description of an ECHONET Lite property.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EpcDescriptor {
+    /// Appliance class of the property. `None` for properties common to all classes.
+    pub class: Option<ApplianceType>,
+    pub epc: u32,
+    pub name: &'static str,
+    pub unit: PropertyUnit,
+    /// Decimal exponent of the value. Physical value is `raw * 10^scale`.
+    pub scale: i8,
+    pub encoding: PropertyEncoding,
+}
+
+/// Decoded value of an ECHONET Lite property.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PropertyValue<'a> {
+    /// Integer value, physical value is `raw * 10^scale`.
+    Integer { raw: i64, scale: i8 },
+    Hex(&'a str),
+    Enum { code: u32, name: Option<&'static str> },
+}
+
+const OPERATION_STATUS: &[(u32, &str)] = &[(0x30, "on"), (0x31, "off")];
+const FAULT_STATUS: &[(u32, &str)] = &[(0x41, "fault"), (0x42, "no_fault")];
+const ENERGY_UNIT: &[(u32, &str)] = &[
+    (0x00, "1kWh"),
+    (0x01, "0.1kWh"),
+    (0x02, "0.01kWh"),
+    (0x03, "0.001kWh"),
+    (0x04, "0.0001kWh"),
+    (0x0A, "10kWh"),
+    (0x0B, "100kWh"),
+    (0x0C, "1000kWh"),
+    (0x0D, "10000kWh"),
+];
+const CHARGING_STATE: &[(u32, &str)] = &[
+    (0x40, "other"),
+    (0x41, "rapid_charging"),
+    (0x42, "charging"),
+    (0x43, "discharging"),
+    (0x44, "standby"),
+    (0x45, "test"),
+    (0x46, "auto"),
+    (0x47, "idle"),
+    (0x48, "restart"),
+    (0x49, "effective_capacity_recalculation"),
+];
+const VEHICLE_CONNECTION: &[(u32, &str)] = &[
+    (0x30, "not_connected"),
+    (0x40, "connected"),
+    (0x41, "chargeable"),
+    (0x42, "dischargeable"),
+    (0x43, "chargeable_and_dischargeable"),
+];
+const AUTOMATIC_WATER_HEATING: &[(u32, &str)] = &[
+    (0x41, "auto"),
+    (0x42, "manual_heating"),
+    (0x43, "manual_stop"),
+];
+const WATER_HEATING_STATUS: &[(u32, &str)] = &[(0x41, "heating"), (0x42, "not_heating")];
+
+const fn epc(
+    class: Option<ApplianceType>,
+    epc: u32,
+    name: &'static str,
+    unit: PropertyUnit,
+    scale: i8,
+    encoding: PropertyEncoding,
+) -> EpcDescriptor {
+    EpcDescriptor { class, epc, name, unit, scale, encoding }
+}
+
+/// All properties known by the registry.
+pub static EPC_REGISTRY: &[EpcDescriptor] = {
+    use ApplianceType::*;
+    use PropertyEncoding::*;
+    use PropertyUnit as U;
+    &[
+        // Super class
+        epc(None, 0x80, "operation_status", U::None, 0, Enum(OPERATION_STATUS)),
+        epc(None, 0x88, "fault_status", U::None, 0, Enum(FAULT_STATUS)),
+        // Low voltage smart electric energy meter
+        epc(Some(SmartMeter), 0xD3, "coefficient", U::None, 0, Decimal),
+        epc(Some(SmartMeter), 0xD7, "cumulative_electric_energy_effective_digits", U::None, 0, Decimal),
+        epc(Some(SmartMeter), 0xE0, "normal_direction_cumulative_electric_energy", U::None, 0, Decimal),
+        epc(Some(SmartMeter), 0xE1, "cumulative_electric_energy_unit", U::None, 0, Enum(ENERGY_UNIT)),
+        epc(Some(SmartMeter), 0xE3, "reverse_direction_cumulative_electric_energy", U::None, 0, Decimal),
+        epc(Some(SmartMeter), 0xE7, "measured_instantaneous", U::Watt, 0, Signed),
+        epc(Some(SmartMeter), 0xE8, "measured_instantaneous_currents", U::None, 0, Hex),
+        // Storage battery
+        epc(Some(StorageBattery), 0xCF, "working_operation_status", U::None, 0, Enum(CHARGING_STATE)),
+        epc(Some(StorageBattery), 0xD3, "measured_instantaneous_charging_discharging_electric_power", U::Watt, 0, Signed),
+        epc(Some(StorageBattery), 0xDA, "operation_mode_setting", U::None, 0, Enum(CHARGING_STATE)),
+        epc(Some(StorageBattery), 0xE2, "remaining_stored_electricity1", U::WattHour, 0, Decimal),
+        epc(Some(StorageBattery), 0xE4, "remaining_stored_electricity3", U::Percent, 0, Decimal),
+        // Household solar power generation
+        epc(Some(SolarPower), 0xE0, "measured_instantaneous_amount_of_electricity_generated", U::Watt, 0, Decimal),
+        epc(Some(SolarPower), 0xE1, "measured_cumulative_amount_of_electric_energy_generated", U::KiloWattHour, -3, Decimal),
+        epc(Some(SolarPower), 0xE3, "measured_cumulative_amount_of_electric_energy_sold", U::KiloWattHour, -3, Decimal),
+        // Electric water heater
+        epc(Some(ElectricWaterHeater), 0xB0, "automatic_water_heating_setting", U::None, 0, Enum(AUTOMATIC_WATER_HEATING)),
+        epc(Some(ElectricWaterHeater), 0xB2, "water_heating_status", U::None, 0, Enum(WATER_HEATING_STATUS)),
+        epc(Some(ElectricWaterHeater), 0xE1, "measured_amount_of_water_remaining_in_tank", U::Liter, 0, Decimal),
+        epc(Some(ElectricWaterHeater), 0xE2, "tank_capacity", U::Liter, 0, Decimal),
+        // Electric vehicle charger/discharger
+        epc(Some(EVCD), 0xC7, "vehicle_connection_and_chargeable_dischargeable_status", U::None, 0, Enum(VEHICLE_CONNECTION)),
+        epc(Some(EVCD), 0xD3, "measured_instantaneous_charging_discharging_electric_power", U::Watt, 0, Signed),
+        epc(Some(EVCD), 0xDA, "operation_mode_setting", U::None, 0, Enum(CHARGING_STATE)),
+        epc(Some(EVCD), 0xE2, "remaining_battery_capacity1", U::WattHour, 0, Decimal),
+        epc(Some(EVCD), 0xE4, "remaining_battery_capacity3", U::Percent, 0, Decimal),
+    ]
+};
+
+/// Look up the descriptor of the property. Properties common to all classes are used if the class has no such property.
+pub fn lookup_epc(class: &ApplianceType, epc: u32) -> Option<&'static EpcDescriptor> {
+    EPC_REGISTRY
+        .iter()
+        .find(|d| d.epc == epc && d.class.as_ref() == Some(class))
+        .or_else(|| EPC_REGISTRY.iter().find(|d| d.epc == epc && d.class.is_none()))
+}
+
+impl EpcDescriptor {
+    /// Decode the value of the property.
+    pub fn decode<'a>(&self, val: &'a str) -> Result<PropertyValue<'a>, ModelNodeParseError> {
+        match self.encoding {
+            PropertyEncoding::Decimal => val
+                .parse::<u32>()
+                .map(|raw| PropertyValue::Integer { raw: raw as i64, scale: self.scale })
+                .map_err(|_| ModelNodeParseError::InvalidPropertyValue),
+            PropertyEncoding::Signed => val
+                .parse::<i64>()
+                .map(|raw| PropertyValue::Integer { raw, scale: self.scale })
+                .map_err(|_| ModelNodeParseError::InvalidPropertyValue),
+            PropertyEncoding::Hex => {
+                if !val.is_empty() && val.chars().all(|c| c.is_ascii_hexdigit()) {
+                    Ok(PropertyValue::Hex(val))
+                } else {
+                    Err(ModelNodeParseError::InvalidPropertyValue)
+                }
+            }
+            PropertyEncoding::Enum(names) => {
+                let code = val.parse::<u32>().map_err(|_| ModelNodeParseError::InvalidPropertyValue)?;
+                let name = names.iter().find(|(c, _)| *c == code).map(|(_, name)| *name);
+                Ok(PropertyValue::Enum { code, name })
+            }
+        }
+    }
+    /// Render the value of the property with its unit.
+    pub fn display<'a>(&self, val: &'a str) -> Result<PropertyDisplay<'a>, ModelNodeParseError> {
+        Ok(PropertyDisplay {
+            value: self.decode(val)?,
+            unit: self.unit,
+        })
+    }
+}
+
+impl<C: Capacities> EchonetLiteProperty<C> {
+    /// Look up the descriptor of this property for the appliance class.
+    pub fn descriptor(&self, class: &ApplianceType) -> Option<&'static EpcDescriptor> {
+        lookup_epc(class, self.epc)
+    }
+}
+
+/// Displays a decoded property value with its unit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PropertyDisplay<'a> {
+    pub value: PropertyValue<'a>,
+    pub unit: PropertyUnit,
+}
+
+impl<'a> Display for PropertyDisplay<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.value {
+            // Values which do not fit in 64 bits are written in the exponent notation, e.g. `5e-128`.
+            PropertyValue::Integer { raw, scale } if scale >= 0 => {
+                match 10i64.checked_pow(scale as u32).and_then(|multiplier| raw.checked_mul(multiplier)) {
+                    Some(value) => write!(f, "{}", value)?,
+                    None => write!(f, "{}e{}", raw, scale)?,
+                }
+            }
+            PropertyValue::Integer { raw, scale } => {
+                let digits = u32::from(scale.unsigned_abs());
+                match 10u64.checked_pow(digits) {
+                    Some(divisor) => {
+                        let sign = if raw < 0 { "-" } else { "" };
+                        let abs = raw.unsigned_abs();
+                        write!(f, "{}{}.{:0width$}", sign, abs / divisor, abs % divisor, width = digits as usize)?
+                    }
+                    None => write!(f, "{}e{}", raw, scale)?,
+                }
+            }
+            PropertyValue::Hex(s) => write!(f, "0x{}", s)?,
+            PropertyValue::Enum { name: Some(name), .. } => write!(f, "{}", name)?,
+            PropertyValue::Enum { code, name: None } => write!(f, "0x{:02X}", code)?,
+        }
+        if self.unit != PropertyUnit::None {
+            write!(f, " {}", self.unit.symbol())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::fmt::Write;
+    use heapless::String;
+
+    use super::*;
+
+    fn render(class: ApplianceType, epc: u32, val: &str) -> String<64> {
+        let mut s = String::new();
+        let descriptor = lookup_epc(&class, epc).unwrap();
+        write!(&mut s, "{}", descriptor.display(val).unwrap()).unwrap();
+        s
+    }
+
+    #[test]
+    fn test_lookup_epc() {
+        let descriptor = lookup_epc(&ApplianceType::SmartMeter, 0xE7).unwrap();
+        assert_eq!(descriptor.name, "measured_instantaneous");
+        assert_eq!(descriptor.unit, PropertyUnit::Watt);
+        let descriptor = lookup_epc(&ApplianceType::SolarPower, 0x80).unwrap();
+        assert_eq!(descriptor.class, None);
+        assert!(lookup_epc(&ApplianceType::SmartMeter, 0xFF).is_none());
+    }
+
+    #[test]
+    fn test_render_property() {
+        assert_eq!(render(ApplianceType::SmartMeter, 0xE7, "397").as_str(), "397 W");
+        assert_eq!(render(ApplianceType::SmartMeter, 0xE1, "2").as_str(), "0.01kWh");
+        assert_eq!(render(ApplianceType::SolarPower, 0xE1, "123456").as_str(), "123.456 kWh");
+        assert_eq!(render(ApplianceType::StorageBattery, 0xD3, "-1200").as_str(), "-1200 W");
+        assert_eq!(render(ApplianceType::StorageBattery, 0xCF, "99").as_str(), "0x63");
+        assert_eq!(render(ApplianceType::EVCD, 0x80, "48").as_str(), "on");
+        assert_eq!(render(ApplianceType::SmartMeter, 0xE8, "00127FFE").as_str(), "0x00127FFE");
+    }
+
+    #[test]
+    fn test_render_extreme_scale() {
+        let render = |raw, scale| {
+            let mut s = String::<64>::new();
+            write!(&mut s, "{}", PropertyDisplay { value: PropertyValue::Integer { raw, scale }, unit: PropertyUnit::None }).unwrap();
+            s
+        };
+        assert_eq!(render(1, 18).as_str(), "1000000000000000000");
+        assert_eq!(render(1, 19).as_str(), "1e19");
+        assert_eq!(render(i64::MAX, 1).as_str(), "9223372036854775807e1");
+        assert_eq!(render(-5, -19).as_str(), "-0.0000000000000000005");
+        assert_eq!(render(5, -20).as_str(), "5e-20");
+        assert_eq!(render(5, i8::MIN).as_str(), "5e-128");
+    }
+
+    #[test]
+    fn test_decode_invalid_value() {
+        let descriptor = lookup_epc(&ApplianceType::SmartMeter, 0xE0).unwrap();
+        assert!(matches!(
+            descriptor.decode("-1"),
+            Err(ModelNodeParseError::InvalidPropertyValue)
+        ));
+    }
+}
@@ -11,14 +11,60 @@
 //!
 
 #![no_std]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
 pub mod config;
+#[cfg(feature = "alloc")]
+pub mod owned;
 mod device;
 mod appliances;
+mod echonet_lite;
+mod epc_registry;
+mod energy_aggregator;
+mod tariff;
+mod comfort;
+mod sensor_history;
+mod occupancy;
+mod lighting;
+mod calibration;
+mod device_kind;
+mod writer;
+mod diagnostics;
+mod location;
+mod statistics;
+mod unknown_keys;
+mod schema;
+mod numeric;
 mod common_types;
 mod node_key;
 mod parser_options;
+#[cfg(feature = "serde")]
+mod serde_impls;
 
 pub use device::*;
 pub use appliances::*;
+pub use echonet_lite::*;
+pub use epc_registry::*;
+pub use energy_aggregator::*;
+pub use tariff::*;
+pub use comfort::*;
+pub use sensor_history::*;
+pub use occupancy::*;
+pub use lighting::*;
+pub use calibration::*;
+pub use device_kind::*;
+pub use writer::*;
+pub use diagnostics::*;
+pub use location::{ErrorLocation, PathSegment, ReadError};
+pub use statistics::ReadStatistics;
+pub use unknown_keys::*;
+pub use schema::{SchemaViolation, ViolationKind, ViolationSink, Violations, REMO_API_UNMODELED_KEYS};
 pub use common_types::*;
-pub use parser_options::ParserOptions;
\ No newline at end of file
+pub use parser_options::{ParserOptions, TruncationMode};
+pub use node_key::{FieldMask, ModelNodeKey};
+pub use config::{Capacities, DefaultCapacities};
+#[cfg(feature = "alloc")]
+pub use config::AllocCapacities;
\ No newline at end of file
@@ -0,0 +1,210 @@
+// Day/night classification and lights-left-on detection from illumination.
+// Copyright 2022-2023 Kenta Ida 
+// SPDX-License-Identifier: MIT
+//
+use chrono::Duration;
+use heapless::Vec;
+use uuid::Uuid;
+
+use crate::appliances::{Appliance, ApplianceSubNode, ApplianceType};
+use crate::common_types::*;
+use crate::config::Capacities;
+use crate::occupancy::Occupancy;
+use crate::sensor_history::{DeviceHistory, SensorKind};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DayNight {
+    Day,
+    Night,
+}
+
+/// Thresholds of illumination in the raw value of the `il` sensor.
+/// Artificial lighting at the device is expected to be between `night` and `day`,
+/// so turning on lights at night does not change the classification.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightingThresholds {
+    /// Classified as day if the mean illumination is at or above this value.
+    pub day: f32,
+    /// Classified as night if the mean illumination is at or below this value.
+    pub night: f32,
+    /// Lights are regarded as on if the latest illumination is at or above this value at night.
+    pub lights_on: f32,
+    /// Window to average the illumination.
+    pub window: Duration,
+}
+
+impl Default for LightingThresholds {
+    fn default() -> Self {
+        Self {
+            day: 300.0,
+            night: 10.0,
+            lights_on: 50.0,
+            window: Duration::minutes(10),
+        }
+    }
+}
+
+/// Lighting status of a device.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LightingStatus {
+    /// `None` until the illumination crosses either threshold.
+    pub day_night: Option<DayNight>,
+    /// Lights seem to be on at night in an empty room.
+    pub lights_left_on: bool,
+}
+
+/// Classifies day/night with hysteresis and detects lights left on for a device.
+pub struct LightingDetector {
+    thresholds: LightingThresholds,
+    day_night: Option<DayNight>,
+}
+
+impl LightingDetector {
+    pub fn new(thresholds: LightingThresholds) -> Self {
+        Self {
+            thresholds,
+            day_night: None,
+        }
+    }
+
+    pub fn day_night(&self) -> Option<DayNight> {
+        self.day_night
+    }
+
+    /// Update the status with the sensor history and the occupancy of the device at `now`.
+    /// `has_light` tells whether a light appliance is registered to the device (see `LightDevices`).
+    pub fn update<const N: usize>(
+        &mut self,
+        history: &DeviceHistory<N>,
+        occupancy: Occupancy,
+        has_light: bool,
+        now: Timestamp,
+    ) -> LightingStatus {
+        if let Some(stats) = history.stats(SensorKind::Illumination, now - self.thresholds.window) {
+            if stats.mean >= self.thresholds.day {
+                self.day_night = Some(DayNight::Day);
+            } else if stats.mean <= self.thresholds.night {
+                self.day_night = Some(DayNight::Night);
+            }
+        }
+        let is_lit = history
+            .latest(SensorKind::Illumination)
+            .map(|v| v.val >= self.thresholds.lights_on)
+            .unwrap_or(false);
+        LightingStatus {
+            day_night: self.day_night,
+            lights_left_on: has_light
+                && occupancy == Occupancy::Vacant
+                && self.day_night == Some(DayNight::Night)
+                && is_lit,
+        }
+    }
+}
+
+/// Collects IDs of devices which have a light appliance, from the callbacks of `read_appliances`.
+pub struct LightDevices<const N: usize> {
+    ids: Vec<Uuid, N>,
+    device: Option<Uuid>,
+}
+
+impl<const N: usize> Default for LightDevices<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> LightDevices<N> {
+    pub const fn new() -> Self {
+        Self {
+            ids: Vec::new(),
+            device: None,
+        }
+    }
+
+    /// Feed a callback of `read_appliances`. Devices exceeding the capacity are ignored.
+    pub fn update<C: Capacities>(&mut self, appliance: &Appliance<C>, sub_node: Option<&ApplianceSubNode<C>>) {
+        match sub_node {
+            Some(ApplianceSubNode::Device(device)) => self.device = Some(device.id),
+            Some(_) => {}
+            None => {
+                // The appliance map ends, so its type is known here.
+                if let Some(id) = self.device.take() {
+                    if appliance.type_ == ApplianceType::Light && !self.contains(&id) {
+                        self.ids.push(id).ok();
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn contains(&self, id: &Uuid) -> bool {
+        self.ids.contains(id)
+    }
+
+    pub fn ids(&self) -> &[Uuid] {
+        &self.ids
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::str::FromStr;
+    use uuid::uuid;
+
+    use super::*;
+    use crate::device::{Device, NewestEvents, SensorValue};
+    use crate::sensor_history::SensorHistory;
+
+    fn illumination(t: &str, val: f32) -> NewestEvents {
+        NewestEvents {
+            illumination: Some(SensorValue {
+                val,
+                created_at: Timestamp::from_str(t).unwrap(),
+            }),
+            ..Default::default()
+        }
+    }
+    fn at(t: &str) -> Timestamp {
+        Timestamp::from_str(t).unwrap()
+    }
+
+    #[test]
+    fn test_light_devices() {
+        let id = uuid!("8afdef94-43f7-4a16-b499-fbb6286f7438");
+        let device: Device = Device { id, ..Default::default() };
+        let mut lights = LightDevices::<4>::new();
+        let ac = Appliance { type_: ApplianceType::AC, ..Default::default() };
+        lights.update(&ac, Some(&ApplianceSubNode::Device(device.clone())));
+        lights.update(&ac, None);
+        assert!(!lights.contains(&id));
+        let light = Appliance { type_: ApplianceType::Light, ..Default::default() };
+        lights.update(&light, Some(&ApplianceSubNode::Device(device)));
+        lights.update(&light, None);
+        assert!(lights.contains(&id));
+    }
+
+    #[test]
+    fn test_lights_left_on() {
+        let id = uuid!("8afdef94-43f7-4a16-b499-fbb6286f7438");
+        let mut history = SensorHistory::<1, 8>::new();
+        let mut detector = LightingDetector::new(LightingThresholds::default());
+
+        history.ingest(&id, &illumination("2022-10-20T03:00:00Z", 800.0));
+        let status = detector.update(history.device(&id).unwrap(), Occupancy::Vacant, true, at("2022-10-20T03:01:00Z"));
+        assert_eq!(status, LightingStatus { day_night: Some(DayNight::Day), lights_left_on: false });
+
+        history.ingest(&id, &illumination("2022-10-20T10:00:00Z", 2.0));
+        let status = detector.update(history.device(&id).unwrap(), Occupancy::Vacant, true, at("2022-10-20T10:01:00Z"));
+        assert_eq!(status, LightingStatus { day_night: Some(DayNight::Night), lights_left_on: false });
+
+        // Lights are turned on at night, and the room becomes empty.
+        history.ingest(&id, &illumination("2022-10-20T11:00:00Z", 150.0));
+        let device = history.device(&id).unwrap();
+        let status = detector.update(device, Occupancy::Occupied, true, at("2022-10-20T11:01:00Z"));
+        assert_eq!(status, LightingStatus { day_night: Some(DayNight::Night), lights_left_on: false });
+        let status = detector.update(device, Occupancy::Vacant, true, at("2022-10-20T11:30:00Z"));
+        assert_eq!(status, LightingStatus { day_night: Some(DayNight::Night), lights_left_on: true });
+        let status = detector.update(device, Occupancy::Vacant, false, at("2022-10-20T11:30:00Z"));
+        assert!(!status.lights_left_on);
+    }
+}
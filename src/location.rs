@@ -0,0 +1,291 @@
+// Locations of parse errors in a JSON document.
+// Copyright 2022-2023 Kenta Ida 
+// SPDX-License-Identifier: MIT
+//
+use core::fmt;
+
+use embedded_io::blocking::Read;
+use embedded_io::Io;
+use fuga_json_seq_parser::{JsonNode, JsonScalarValue};
+use fuga_json_seq_parser::ParserError as JsonParserError;
+use heapless::Vec;
+
+use crate::common_types::ModelNodeParseError;
+use crate::config::MAX_ERROR_PATH_DEPTH;
+use crate::node_key::ModelNodeKey;
+
+/// A segment of the JSON path to a node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathSegment {
+    /// Index of an element in an array.
+    Index(usize),
+    Key(ModelNodeKey),
+    /// Key not known by the parser, or a map before its first key.
+    UnknownKey,
+}
+
+/// Location of the node at which parsing failed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ErrorLocation {
+    /// Number of bytes read from the stream. As the parser reads ahead into its buffer,
+    /// the node is located within the parser buffer length before this offset.
+    pub offset: usize,
+    /// JSON path to the node, e.g. `[3].smart_meter.echonetlite_properties[2].epc`.
+    pub path: Vec<PathSegment, MAX_ERROR_PATH_DEPTH>,
+    /// The node is deeper than `MAX_ERROR_PATH_DEPTH` and `path` holds only its ancestors.
+    pub truncated: bool,
+}
+
+impl ErrorLocation {
+    /// Index of the device or the appliance in the document.
+    pub fn index(&self) -> Option<usize> {
+        match self.path.first() {
+            Some(PathSegment::Index(index)) => Some(*index),
+            _ => None,
+        }
+    }
+}
+
+/// Format a JSON path as `[3].smart_meter.echonetlite_properties`.
+pub(crate) fn write_path(f: &mut fmt::Formatter<'_>, path: &[PathSegment]) -> fmt::Result {
+    for (position, segment) in path.iter().enumerate() {
+        let separator = if position == 0 { "" } else { "." };
+        match segment {
+            PathSegment::Index(index) => write!(f, "[{}]", index)?,
+            PathSegment::Key(key) => write!(f, "{}{}", separator, key.as_str())?,
+            PathSegment::UnknownKey => write!(f, "{}?", separator)?,
+        }
+    }
+    Ok(())
+}
+
+/// Format as `<path> at byte <offset>`.
+impl fmt::Display for ErrorLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            f.write_str("<root>")?;
+        }
+        write_path(f, &self.path)?;
+        if self.truncated {
+            f.write_str("...")?;
+        }
+        write!(f, " at byte {}", self.offset)
+    }
+}
+
+/// Error of the `read_*` functions with its location.
+#[derive(Debug)]
+pub struct ReadError<E> {
+    pub error: JsonParserError<E, ModelNodeParseError>,
+    pub location: ErrorLocation,
+}
+
+/// Format as `<error> at <location>`.
+impl<E: fmt::Debug> fmt::Display for ReadError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.error {
+            JsonParserError::InputError(error) => write!(f, "input error: {:?}", error)?,
+            JsonParserError::Fail(kind) => write!(f, "invalid JSON: {:?}", kind)?,
+            JsonParserError::Callback(error) => write!(f, "{}", error)?,
+            JsonParserError::ValueTooLong => f.write_str("value too long for the parser buffer")?,
+        }
+        write!(f, " at {}", self.location)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: fmt::Debug> std::error::Error for ReadError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.error {
+            JsonParserError::Callback(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Reader which counts the bytes read from the inner reader.
+pub(crate) struct CountingReader<'a, R> {
+    inner: &'a mut R,
+    bytes_read: usize,
+}
+
+impl<'a, R: Read> CountingReader<'a, R> {
+    pub fn new(inner: &'a mut R) -> Self {
+        Self { inner, bytes_read: 0 }
+    }
+
+    pub fn bytes_read(&self) -> usize {
+        self.bytes_read
+    }
+}
+
+impl<R: Read> Io for CountingReader<'_, R> {
+    type Error = R::Error;
+}
+
+impl<R: Read> Read for CountingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let length = self.inner.read(buf)?;
+        self.bytes_read += length;
+        Ok(length)
+    }
+}
+
+/// Tracks the JSON path of the nodes from the parser.
+#[derive(Default)]
+pub(crate) struct PathTracker {
+    path: Vec<PathSegment, MAX_ERROR_PATH_DEPTH>,
+    /// Depth of the current container, which can be deeper than `path`.
+    depth: usize,
+    /// An element of the current array has ended, so the index advances at the next node.
+    advance: bool,
+    /// Maximum depth reached.
+    max_depth: usize,
+}
+
+impl PathTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Segment of the current container, unless it is deeper than the path can hold.
+    fn top_mut(&mut self) -> Option<&mut PathSegment> {
+        if self.depth == self.path.len() {
+            self.path.last_mut()
+        } else {
+            None
+        }
+    }
+
+    fn push(&mut self, segment: PathSegment) {
+        if self.depth == self.path.len() {
+            self.path.push(segment).ok();
+        }
+        self.depth += 1;
+        self.max_depth = self.max_depth.max(self.depth);
+    }
+
+    fn pop(&mut self) {
+        if self.depth == self.path.len() {
+            self.path.pop();
+        }
+        self.depth = self.depth.saturating_sub(1);
+    }
+
+    /// Path of the current container, or of its ancestors if it is deeper than the path can hold.
+    pub fn container_path(&self) -> &[PathSegment] {
+        if self.depth == self.path.len() {
+            &self.path[..self.path.len().saturating_sub(1)]
+        } else {
+            &self.path
+        }
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Update the path with a node before processing it, so the path points the node.
+    pub fn update(&mut self, node: &JsonNode) {
+        if core::mem::take(&mut self.advance) {
+            if let Some(PathSegment::Index(index)) = self.top_mut() {
+                *index += 1;
+            }
+        }
+        match node {
+            JsonNode::StartMap => self.push(PathSegment::UnknownKey),
+            JsonNode::StartArray => self.push(PathSegment::Index(0)),
+            JsonNode::EndMap | JsonNode::EndArray => {
+                self.pop();
+                self.advance = true;
+            }
+            JsonNode::Key(key) => {
+                let segment = match key {
+                    JsonScalarValue::String(key) => {
+                        ModelNodeKey::try_from(*key).map_or(PathSegment::UnknownKey, PathSegment::Key)
+                    }
+                    _ => PathSegment::UnknownKey,
+                };
+                if let Some(top) = self.top_mut() {
+                    *top = segment;
+                }
+            }
+            JsonNode::Value(JsonScalarValue::ContinuingString(_, _, false)) => {}
+            JsonNode::Value(_) => self.advance = true,
+        }
+    }
+
+    /// Mark the key of the current map as unknown, as it is not known in the map though known in others.
+    pub fn mark_unknown_key(&mut self) {
+        if let Some(top) = self.top_mut() {
+            *top = PathSegment::UnknownKey;
+        }
+    }
+
+    /// Path to the current node, or of its ancestors if it is deeper than the path can hold.
+    pub fn path(&self) -> &[PathSegment] {
+        &self.path
+    }
+
+    /// Attach the current location to an error.
+    pub fn error<E>(&self, error: JsonParserError<E, ModelNodeParseError>, offset: usize) -> ReadError<E> {
+        ReadError {
+            error,
+            location: ErrorLocation {
+                offset,
+                path: self.path.clone(),
+                truncated: self.depth > self.path.len(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn format(location: &ErrorLocation) -> heapless::String<64> {
+        let mut s = heapless::String::new();
+        fmt::Write::write_fmt(&mut s, format_args!("{}", location)).unwrap();
+        s
+    }
+
+    #[test]
+    fn test_path_tracker() {
+        let mut tracker = PathTracker::new();
+        let string = |s| JsonScalarValue::String(s);
+        let nodes = [
+            JsonNode::StartArray,
+            JsonNode::StartMap,
+            JsonNode::EndMap,
+            JsonNode::StartMap,
+            JsonNode::Key(string("smart_meter")),
+            JsonNode::StartMap,
+            JsonNode::Key(string("echonetlite_properties")),
+            JsonNode::StartArray,
+            JsonNode::Value(string("skipped")),
+            JsonNode::StartMap,
+            JsonNode::Key(string("epc")),
+        ];
+        nodes.iter().for_each(|node| tracker.update(node));
+        let ReadError { location, .. } = tracker.error::<()>(JsonParserError::ValueTooLong, 120);
+        assert_eq!(location.index(), Some(1));
+        assert_eq!(format(&location), "[1].smart_meter.echonetlite_properties[1].epc at byte 120");
+
+        tracker.update(&JsonNode::Value(JsonScalarValue::ContinuingString("abc", 0, false)));
+        tracker.update(&JsonNode::EndMap);
+        tracker.update(&JsonNode::EndArray);
+        tracker.update(&JsonNode::Key(string("unknown")));
+        let ReadError { location, .. } = tracker.error::<()>(JsonParserError::ValueTooLong, 130);
+        assert_eq!(tracker.max_depth(), 5);
+        assert_eq!(format(&location), "[1].smart_meter.? at byte 130");
+
+        let error = ReadError::<()> {
+            error: JsonParserError::Callback(ModelNodeParseError::MacAddressParseError),
+            location,
+        };
+        let mut s = heapless::String::<96>::new();
+        fmt::Write::write_fmt(&mut s, format_args!("{}", error)).unwrap();
+        assert_eq!(s.as_str(), "failed to parse MAC address at [1].smart_meter.? at byte 130");
+    }
+}
@@ -1,5 +1,6 @@
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ModelNodeKey {
     Name,
     Id,
@@ -71,4 +72,90 @@ impl<'a> TryFrom<&'a str> for ModelNodeKey {
             _ => Err(()),
         }
     }
-}
\ No newline at end of file
+}
+
+impl ModelNodeKey {
+    const fn bit(self) -> u64 {
+        1 << self as u64
+    }
+
+    /// Key in the JSON document.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Id => "id",
+            Self::CreatedAt => "created_at",
+            Self::UpdatedAt => "updated_at",
+            Self::MacAddress => "mac_address",
+            Self::BtMacAddress => "bt_mac_address",
+            Self::SerialNumber => "serial_number",
+            Self::FirmwareVersion => "firmware_version",
+            Self::TemperatureOffset => "temperature_offset",
+            Self::HumidityOffset => "humidity_offset",
+            Self::Users => "users",
+            Self::NickName => "nickname",
+            Self::SuperUser => "superuser",
+            Self::NewestEvents => "newest_events",
+            Self::Te => "te",
+            Self::Hu => "hu",
+            Self::Il => "il",
+            Self::Mo => "mo",
+            Self::Val => "val",
+            Self::Device => "device",
+            Self::Model => "model",
+            Self::Type => "type",
+            Self::Manufacturer => "manufacturer",
+            Self::Country => "country",
+            Self::RemoteName => "remote_name",
+            Self::Series => "series",
+            Self::Image => "image",
+            Self::SmartMeter => "smart_meter",
+            Self::EchonetLiteProperties => "echonetlite_properties",
+            Self::Epc => "epc",
+        }
+    }
+}
+
+/// Set of `ModelNodeKey` to select the fields to parse.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FieldMask(u64);
+
+impl FieldMask {
+    pub const ALL: Self = Self(u64::MAX);
+    pub const NONE: Self = Self(0);
+
+    pub const fn with(self, key: ModelNodeKey) -> Self {
+        Self(self.0 | key.bit())
+    }
+
+    pub const fn without(self, key: ModelNodeKey) -> Self {
+        Self(self.0 & !key.bit())
+    }
+
+    pub const fn contains(&self, key: ModelNodeKey) -> bool {
+        self.0 & key.bit() != 0
+    }
+
+    /// Set of `keys`, usable in constants.
+    pub const fn from_keys(keys: &[ModelNodeKey]) -> Self {
+        let mut mask = Self::NONE;
+        let mut index = 0;
+        while index < keys.len() {
+            mask = mask.with(keys[index]);
+            index += 1;
+        }
+        mask
+    }
+}
+
+impl Default for FieldMask {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl FromIterator<ModelNodeKey> for FieldMask {
+    fn from_iter<I: IntoIterator<Item = ModelNodeKey>>(iter: I) -> Self {
+        iter.into_iter().fold(Self::NONE, Self::with)
+    }
+}
@@ -0,0 +1,84 @@
+// Coercion of JSON scalars to the numeric fields of the models.
+// Copyright 2022-2023 Kenta Ida 
+// SPDX-License-Identifier: MIT
+//
+use fuga_json_seq_parser::{JsonNumber, JsonScalarValue};
+
+use crate::common_types::ModelNodeParseError;
+
+/// Numeric field type which a JSON number of any shape is converted to.
+pub(crate) trait JsonNumeric: Sized {
+    fn from_number(n: JsonNumber) -> Result<Self, ModelNodeParseError>;
+}
+
+impl JsonNumeric for f32 {
+    fn from_number(n: JsonNumber) -> Result<Self, ModelNodeParseError> {
+        let value: f32 = n.into();
+        if value.is_finite() {
+            Ok(value)
+        } else {
+            Err(ModelNodeParseError::NumberOutOfRange)
+        }
+    }
+}
+
+impl JsonNumeric for u32 {
+    fn from_number(n: JsonNumber) -> Result<Self, ModelNodeParseError> {
+        let integer = match n {
+            JsonNumber::I32(n) => u32::try_from(n).ok(),
+            JsonNumber::U32(n) => Some(n),
+            JsonNumber::I64(n) => u32::try_from(n).ok(),
+            JsonNumber::U64(n) => u32::try_from(n).ok(),
+            // Floats are accepted only if they are integral, e.g. `211.0`.
+            // The range is checked first, as the cast saturates, e.g. 2^32 as f32 to `u32::MAX` which rounds back to 2^32.
+            JsonNumber::F32(n) => Some(n).filter(|n| (0.0..4294967296.0).contains(n)).map(|n| n as u32).filter(|integer| *integer as f32 == n),
+            JsonNumber::F64(n) => Some(n).filter(|n| (0.0..4294967296.0).contains(n)).map(|n| n as u32).filter(|integer| *integer as f64 == n),
+        };
+        integer.ok_or(ModelNodeParseError::NumberOutOfRange)
+    }
+}
+
+/// Convert an integer, a float or a numeric string to `T`. `null` is an invalid number, not the default value of `T`.
+pub(crate) fn coerce_number<T: JsonNumeric>(value: &JsonScalarValue) -> Result<T, ModelNodeParseError> {
+    match value {
+        JsonScalarValue::Number(n) => T::from_number(*n),
+        JsonScalarValue::String(s) => {
+            let n = JsonNumber::try_parse(s.trim()).map_err(|_| ModelNodeParseError::InvalidNumber)?;
+            T::from_number(n)
+        }
+        _ => Err(ModelNodeParseError::InvalidNumber),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_coerce_number() {
+        let epc = |value| coerce_number::<u32>(&value);
+        assert_eq!(epc(JsonScalarValue::Number(JsonNumber::I32(211))), Ok(211));
+        assert_eq!(epc(JsonScalarValue::Number(JsonNumber::U64(0x1_0000_0000))), Err(ModelNodeParseError::NumberOutOfRange));
+        assert_eq!(epc(JsonScalarValue::Number(JsonNumber::I32(-1))), Err(ModelNodeParseError::NumberOutOfRange));
+        assert_eq!(epc(JsonScalarValue::Number(JsonNumber::F64(211.0))), Ok(211));
+        assert_eq!(epc(JsonScalarValue::Number(JsonNumber::F64(211.5))), Err(ModelNodeParseError::NumberOutOfRange));
+        // Boundaries of u32, where a saturating cast would round back to the float.
+        assert_eq!(epc(JsonScalarValue::Number(JsonNumber::F32(4294967296.0))), Err(ModelNodeParseError::NumberOutOfRange));
+        assert_eq!(epc(JsonScalarValue::Number(JsonNumber::F32(4294967040.0))), Ok(4294967040));
+        assert_eq!(epc(JsonScalarValue::Number(JsonNumber::F64(4294967296.0))), Err(ModelNodeParseError::NumberOutOfRange));
+        assert_eq!(epc(JsonScalarValue::Number(JsonNumber::F64(4294967295.0))), Ok(u32::MAX));
+        assert_eq!(epc(JsonScalarValue::Number(JsonNumber::F32(-0.0))), Ok(0));
+        assert_eq!(epc(JsonScalarValue::Number(JsonNumber::F32(f32::NAN))), Err(ModelNodeParseError::NumberOutOfRange));
+        assert_eq!(epc(JsonScalarValue::String("211")), Ok(211));
+        assert_eq!(epc(JsonScalarValue::String("0xd3")), Err(ModelNodeParseError::InvalidNumber));
+        assert_eq!(epc(JsonScalarValue::Null), Err(ModelNodeParseError::InvalidNumber));
+        assert_eq!(epc(JsonScalarValue::Boolean(true)), Err(ModelNodeParseError::InvalidNumber));
+
+        let offset = |value| coerce_number::<f32>(&value);
+        assert_eq!(offset(JsonScalarValue::Number(JsonNumber::I32(-1))), Ok(-1.0));
+        assert_eq!(offset(JsonScalarValue::String(" -0.5 ")), Ok(-0.5));
+        assert_eq!(offset(JsonScalarValue::Null), Err(ModelNodeParseError::InvalidNumber));
+        assert_eq!(offset(JsonScalarValue::String("NaN")), Err(ModelNodeParseError::NumberOutOfRange));
+        assert_eq!(offset(JsonScalarValue::Number(JsonNumber::F64(1e300))), Err(ModelNodeParseError::NumberOutOfRange));
+    }
+}
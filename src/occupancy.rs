@@ -0,0 +1,135 @@
+// Occupancy tracker driven by the motion sensor in NewestEvents.
+// Copyright 2022-2023 Kenta Ida 
+// SPDX-License-Identifier: MIT
+//
+use chrono::Duration;
+
+use crate::common_types::*;
+use crate::device::NewestEvents;
+
+/// Occupancy state of the room where the device is placed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Occupancy {
+    /// No motion event has been observed yet.
+    #[default]
+    Unknown,
+    Occupied,
+    Vacant,
+}
+
+/// Event reported by `OccupancyTracker`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OccupancyEvent {
+    /// A new motion was detected at the time.
+    MotionDetected(Timestamp),
+    /// The room became vacant at the time, which is the last motion plus the timeout.
+    Vacated(Timestamp),
+}
+
+/// Tracks occupancy from successive `NewestEvents`.
+/// The `val` of the motion sensor is meaningless, so a motion is detected by the change of its `created_at`.
+pub struct OccupancyTracker {
+    timeout: Duration,
+    last_motion: Option<Timestamp>,
+    state: Occupancy,
+}
+
+impl OccupancyTracker {
+    /// Create a tracker. The room is regarded as vacant if no motion is detected for `timeout`.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            last_motion: None,
+            state: Occupancy::Unknown,
+        }
+    }
+
+    pub fn state(&self) -> Occupancy {
+        self.state
+    }
+
+    /// Timestamp of the last motion.
+    pub fn last_motion(&self) -> Option<Timestamp> {
+        self.last_motion
+    }
+
+    /// Update the state with `NewestEvents` polled at `now`.
+    pub fn update(&mut self, events: &NewestEvents, now: Timestamp) -> Option<OccupancyEvent> {
+        if let Some(motion) = &events.motion {
+            let is_new = self.last_motion.map(|last| last < motion.created_at).unwrap_or(true);
+            if is_new {
+                self.last_motion = Some(motion.created_at);
+                if now - motion.created_at < self.timeout {
+                    self.state = Occupancy::Occupied;
+                    return Some(OccupancyEvent::MotionDetected(motion.created_at));
+                }
+            }
+        }
+        self.check_timeout(now)
+    }
+
+    /// Update the state without new events, e.g. when polling failed.
+    pub fn check_timeout(&mut self, now: Timestamp) -> Option<OccupancyEvent> {
+        let last_motion = self.last_motion?;
+        if now - last_motion < self.timeout {
+            return None;
+        }
+        match self.state {
+            Occupancy::Vacant => None,
+            Occupancy::Unknown => {
+                self.state = Occupancy::Vacant;
+                None
+            }
+            Occupancy::Occupied => {
+                self.state = Occupancy::Vacant;
+                Some(OccupancyEvent::Vacated(last_motion + self.timeout))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::str::FromStr;
+
+    use super::*;
+    use crate::device::SensorValue;
+
+    fn motion(t: &str) -> NewestEvents {
+        NewestEvents {
+            motion: Some(SensorValue {
+                val: 1.0,
+                created_at: Timestamp::from_str(t).unwrap(),
+            }),
+            ..Default::default()
+        }
+    }
+    fn at(t: &str) -> Timestamp {
+        Timestamp::from_str(t).unwrap()
+    }
+
+    #[test]
+    fn test_occupancy() {
+        let mut tracker = OccupancyTracker::new(Duration::minutes(10));
+        assert_eq!(tracker.update(&NewestEvents::default(), at("2022-10-20T22:00:00Z")), None);
+        assert_eq!(tracker.state(), Occupancy::Unknown);
+
+        // An old motion only sets the baseline.
+        assert_eq!(tracker.update(&motion("2022-10-20T20:00:00Z"), at("2022-10-20T22:00:00Z")), None);
+        assert_eq!(tracker.state(), Occupancy::Vacant);
+
+        assert_eq!(
+            tracker.update(&motion("2022-10-20T22:00:30Z"), at("2022-10-20T22:01:00Z")),
+            Some(OccupancyEvent::MotionDetected(at("2022-10-20T22:00:30Z")))
+        );
+        assert_eq!(tracker.state(), Occupancy::Occupied);
+        // Same created_at is not a new motion.
+        assert_eq!(tracker.update(&motion("2022-10-20T22:00:30Z"), at("2022-10-20T22:02:00Z")), None);
+        assert_eq!(
+            tracker.update(&motion("2022-10-20T22:00:30Z"), at("2022-10-20T22:11:00Z")),
+            Some(OccupancyEvent::Vacated(at("2022-10-20T22:10:30Z")))
+        );
+        assert_eq!(tracker.state(), Occupancy::Vacant);
+        assert_eq!(tracker.check_timeout(at("2022-10-20T22:12:00Z")), None);
+    }
+}
@@ -0,0 +1,298 @@
+// Owned models backed by alloc for Remo Cloud API.
+// Copyright 2022-2023 Kenta Ida 
+// SPDX-License-Identifier: MIT
+//
+//! Owned versions of the models whose strings and lists are not limited by the capacities in `config`.
+//!
+//! The documents are read by `read_devices`/`read_appliances` with `AllocCapacities`, so `ParserOptions` applies as well.
+//! Strings longer than the parser buffer are concatenated from their pieces, so they are never truncated
+//! except `serial_number` which is held in `SerialNumber` by the parser.
+
+use alloc::{string::String, vec::Vec};
+use core::cell::RefCell;
+
+use uuid::Uuid;
+
+use crate::appliances::{read_appliances_with_capacities, ApplianceSubNode, ApplianceType};
+use crate::common_types::*;
+use crate::config::{AllocCapacities, Capacities};
+use crate::device::{read_devices_with_ends, DeviceSubNode, MacAddress, NewestEvents};
+use crate::location::ReadError;
+use crate::parser_options::ParserOptions;
+use crate::statistics::ReadStatistics;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct User {
+    pub id: Uuid,
+    pub nickname: String,
+    pub superuser: bool,
+}
+
+/// Device with its `users` and `newest_events`.
+/// The `device` node of an appliance has neither of them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Device {
+    pub id: Uuid,
+    pub name: String,
+    pub temperature_offset: f32,
+    pub humidity_offset: f32,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+    pub firmware_version: String,
+    pub mac_address: MacAddress,
+    pub bt_mac_address: MacAddress,
+    pub serial_number: String,
+    pub users: Vec<User>,
+    pub newest_events: Option<NewestEvents>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EchonetLiteProperty {
+    pub name: String,
+    pub epc: u32,
+    pub val: String,
+    pub updated_at: Timestamp,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ApplianceModel {
+    pub id: Uuid,
+    pub country: String,
+    pub manufacturer: String,
+    pub remote_name: String,
+    pub series: String,
+    pub name: String,
+    pub image: String,
+}
+
+/// Appliance with its sub nodes.
+/// `echonet_lite_properties` holds `smart_meter.echonetlite_properties` of the appliance.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Appliance {
+    pub id: Uuid,
+    pub type_: ApplianceType,
+    pub nickname: String,
+    pub image: String,
+    pub device: Option<Device>,
+    pub model: Option<ApplianceModel>,
+    pub echonet_lite_properties: Vec<EchonetLiteProperty>,
+}
+
+impl<C: Capacities> From<&crate::device::User<C>> for User {
+    fn from(user: &crate::device::User<C>) -> Self {
+        Self {
+            id: user.id,
+            nickname: String::from(&*user.nickname),
+            superuser: user.superuser,
+        }
+    }
+}
+
+impl<C: Capacities> From<&crate::device::Device<C>> for Device {
+    fn from(device: &crate::device::Device<C>) -> Self {
+        Self {
+            id: device.id,
+            name: String::from(&*device.name),
+            temperature_offset: device.temperature_offset,
+            humidity_offset: device.humidity_offset,
+            created_at: device.created_at,
+            updated_at: device.updated_at,
+            firmware_version: String::from(&*device.firmware_version),
+            mac_address: device.mac_address,
+            bt_mac_address: device.bt_mac_address,
+            serial_number: String::from(device.serial_number.as_str()),
+            users: Vec::new(),
+            newest_events: None,
+        }
+    }
+}
+
+impl<C: Capacities> From<&crate::appliances::EchonetLiteProperty<C>> for EchonetLiteProperty {
+    fn from(property: &crate::appliances::EchonetLiteProperty<C>) -> Self {
+        Self {
+            name: String::from(&*property.name),
+            epc: property.epc,
+            val: String::from(&*property.val),
+            updated_at: property.updated_at,
+        }
+    }
+}
+
+impl<C: Capacities> From<&crate::appliances::ApplianceModel<C>> for ApplianceModel {
+    fn from(model: &crate::appliances::ApplianceModel<C>) -> Self {
+        Self {
+            id: model.id,
+            country: String::from(&*model.country),
+            manufacturer: String::from(&*model.manufacturer),
+            remote_name: String::from(&*model.remote_name),
+            series: String::from(&*model.series),
+            name: String::from(&*model.name),
+            image: String::from(&*model.image),
+        }
+    }
+}
+
+impl<C: Capacities> From<&crate::appliances::Appliance<C>> for Appliance {
+    fn from(appliance: &crate::appliances::Appliance<C>) -> Self {
+        Self {
+            id: appliance.id,
+            type_: appliance.type_,
+            nickname: String::from(&*appliance.nickname),
+            image: String::from(&*appliance.image),
+            ..Default::default()
+        }
+    }
+}
+
+/// Read all devices in a `/1/devices` document with their `users` and `newest_events`.
+#[allow(clippy::result_large_err)]
+pub fn read_devices_to_vec<R: embedded_io::blocking::Read>(
+    reader: &mut R,
+    total_length: Option<usize>,
+    options: &ParserOptions,
+) -> Result<(Vec<Device>, ReadStatistics), ReadError<R::Error>> {
+    let mut devices: Vec<Device> = Vec::new();
+    // Sub nodes of the current device, which are passed before the end of the device.
+    let current = RefCell::new(Device::default());
+    let statistics = read_devices_with_ends::<AllocCapacities, _, _, _>(
+        reader,
+        total_length,
+        options,
+        |_, sub_node| match sub_node {
+            Some(DeviceSubNode::User(user)) => current.borrow_mut().users.push(user.into()),
+            Some(DeviceSubNode::NewestEvents(newest_events)) => current.borrow_mut().newest_events = Some(newest_events.clone()),
+            None => {}
+        },
+        |device| {
+            let sub_nodes = current.take();
+            devices.push(Device {
+                users: sub_nodes.users,
+                newest_events: sub_nodes.newest_events,
+                ..device.into()
+            });
+        },
+    )?;
+    Ok((devices, statistics))
+}
+
+/// Read all appliances in a `/1/appliances` document with their sub nodes.
+#[allow(clippy::result_large_err)]
+pub fn read_appliances_to_vec<R: embedded_io::blocking::Read>(
+    reader: &mut R,
+    total_length: Option<usize>,
+    options: &ParserOptions,
+) -> Result<(Vec<Appliance>, ReadStatistics), ReadError<R::Error>> {
+    let mut appliances: Vec<Appliance> = Vec::new();
+    // Sub nodes of the current appliance, which are passed before the appliance.
+    let mut current = Appliance::default();
+    let statistics = read_appliances_with_capacities::<AllocCapacities, _, _>(reader, total_length, options, |appliance, sub_node| match sub_node {
+        Some(ApplianceSubNode::Device(device)) => current.device = Some(device.into()),
+        Some(ApplianceSubNode::Model(model)) => current.model = Some(model.into()),
+        Some(ApplianceSubNode::EchonetLiteProperty(property)) => current.echonet_lite_properties.push(property.into()),
+        None => {
+            let sub_nodes = core::mem::take(&mut current);
+            appliances.push(Appliance {
+                device: sub_nodes.device,
+                model: sub_nodes.model,
+                echonet_lite_properties: sub_nodes.echonet_lite_properties,
+                ..appliance.into()
+            });
+        }
+    })?;
+    Ok((appliances, statistics))
+}
+#[cfg(test)]
+mod test {
+    use fuga_json_seq_parser::BufferReader;
+    use uuid::uuid;
+
+    use super::*;
+
+    #[test]
+    fn test_read_devices_to_vec() {
+        let input = include_str!("../data/devices.json");
+        let devices = read_devices_to_vec(&mut BufferReader::new(input.as_bytes()), Some(input.len()), &ParserOptions::default()).unwrap().0;
+        assert_eq!(devices.len(), 3);
+        assert_eq!(devices[0].id, uuid!("f262cb0c-a853-47bb-9559-44d0f2c4d6e2"));
+        assert_eq!(devices[0].firmware_version, "Remo-mini/1.10.0");
+        assert_eq!(devices[0].users.len(), 1);
+        assert_eq!(devices[0].users[0].nickname, "Hoge Fuga");
+        let newest_events = devices[0].newest_events.as_ref().unwrap();
+        assert_eq!(newest_events.temperature.as_ref().unwrap().val, 24.8);
+        assert!(newest_events.humidity.is_none());
+    }
+
+    #[test]
+    fn test_read_devices_to_vec_long_strings() {
+        let input = r#"[{
+            "name": "A device name which is much longer than the capacity of the heapless model and the parser buffer",
+            "id": "f262cb0c-a853-47bb-9559-44d0f2c4d6e2",
+            "users": [{"id": "50937884-2550-46b1-9b0a-503410c06f6d", "nickname": "ながいながいながいながいながいながいながいニックネーム", "superuser": false}]
+        }]"#;
+        let devices = read_devices_to_vec(&mut BufferReader::new(input.as_bytes()), Some(input.len()), &ParserOptions::default()).unwrap().0;
+        assert_eq!(
+            devices[0].name,
+            "A device name which is much longer than the capacity of the heapless model and the parser buffer"
+        );
+        assert_eq!(devices[0].users[0].nickname, "ながいながいながいながいながいながいながいニックネーム");
+        assert_eq!(devices[0].id, uuid!("f262cb0c-a853-47bb-9559-44d0f2c4d6e2"));
+    }
+
+    #[test]
+    fn test_read_devices_to_vec_options() {
+        // Devices without users are read, and a malformed field is recovered in lenient mode.
+        let input = r#"[{"name":"Remo","id":"invalid","firmware_version":"Remo/1.0.0"},{"name":"Remo mini","users":[]}]"#;
+        let diagnostics = crate::Diagnostics::<2>::new();
+        let (devices, statistics) =
+            read_devices_to_vec(&mut BufferReader::new(input.as_bytes()), Some(input.len()), &ParserOptions::default().lenient(&diagnostics)).unwrap();
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].id, Uuid::default());
+        assert_eq!(devices[1].name, "Remo mini");
+        // Fields of the preceding device are not carried over.
+        assert_eq!(devices[1].firmware_version, "");
+        assert_eq!(statistics.elements, 2);
+        assert_eq!(diagnostics.take()[0].key, crate::ModelNodeKey::Id);
+    }
+
+    #[test]
+    fn test_read_devices_to_vec_unknown_sensors() {
+        let input = r#"[{"newest_events":{"co2":{"val":400},"val":{"val":1},"te":{"val":24.5}}}]"#;
+        let devices = read_devices_to_vec(&mut BufferReader::new(input.as_bytes()), Some(input.len()), &ParserOptions::default()).unwrap().0;
+        let newest_events = devices[0].newest_events.as_ref().unwrap();
+        assert_eq!(newest_events.temperature.as_ref().unwrap().val, 24.5);
+    }
+
+    #[test]
+    fn test_read_appliances_to_vec() {
+        let input = include_str!("../data/appliances.json");
+        let appliances = read_appliances_to_vec(&mut BufferReader::new(input.as_bytes()), Some(input.len()), &ParserOptions::default()).unwrap().0;
+
+        // Same as the heapless parser except the sub nodes are gathered into the appliance.
+        let mut expected: Vec<Appliance> = Vec::new();
+        let mut current = Appliance::default();
+        crate::read_appliances(
+            &mut BufferReader::new(input.as_bytes()),
+            Some(input.len()),
+            &crate::ParserOptions::default(),
+            |appliance, sub_node| match sub_node {
+                Some(crate::ApplianceSubNode::Device(device)) => current.device = Some(device.into()),
+                Some(crate::ApplianceSubNode::Model(model)) => current.model = Some(model.into()),
+                Some(crate::ApplianceSubNode::EchonetLiteProperty(property)) => {
+                    current.echonet_lite_properties.push(property.into())
+                }
+                None => {
+                    let sub_nodes = core::mem::take(&mut current);
+                    expected.push(Appliance {
+                        device: sub_nodes.device,
+                        model: sub_nodes.model,
+                        echonet_lite_properties: sub_nodes.echonet_lite_properties,
+                        ..appliance.into()
+                    });
+                }
+            },
+        )
+        .unwrap();
+        assert_eq!(appliances, expected);
+        assert!(appliances.iter().any(|appliance| !appliance.echonet_lite_properties.is_empty()));
+    }
+}
@@ -5,38 +5,469 @@
 
 use core::str::FromStr;
 
+use fuga_json_seq_parser::JsonScalarValue;
 use heapless::String;
+use unicode_segmentation::{GraphemeCursor, GraphemeIncomplete};
 use crate::common_types::ModelNodeParseError;
+use crate::config::MAX_ERROR_PATH_DEPTH;
+use crate::config::ModelString;
+use crate::diagnostics::{DiagnosticKind, DiagnosticsSink, FieldDiagnostic};
+use crate::location::{PathSegment, PathTracker};
+use crate::statistics::ReadStatistics;
+use crate::unknown_keys::{UnknownKeyKind, UnknownKeySink};
+use crate::schema::{SchemaViolation, ViolationKind, ViolationSink, REMO_API_UNMODELED_KEYS};
+use crate::node_key::{FieldMask, ModelNodeKey};
 
-pub struct ParserOptions {
+pub struct ParserOptions<'a> {
     /// Truncate strings if the length is too long to hold.
     truncate_too_long_string: bool,
+    /// Boundary to truncate strings at.
+    truncation: TruncationMode,
+    /// Keys of the values to parse.
+    fields: FieldMask,
+    /// Replace fields which failed to parse with their default values.
+    lenient: bool,
+    /// Sink of the field failures in lenient mode and the truncated strings.
+    diagnostics: Option<&'a dyn DiagnosticsSink>,
+    /// Sink of the keys not known by the parsers.
+    unknown_keys: Option<&'a dyn UnknownKeySink>,
+    /// Sink of the schema violations in strict mode. `None` if not strict.
+    violations: Option<&'a dyn ViolationSink>,
+    /// Keys of devices and appliances which are in the API but not modeled.
+    unmodeled_keys: &'a [&'a str],
 }
 
-impl Default for ParserOptions {
+/// Boundary to truncate too long strings at.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TruncationMode {
+    /// Truncate at a character boundary.
+    Char,
+    /// Truncate at a boundary of the extended grapheme clusters of Unicode, so a base character is kept with its
+    /// combining marks, variation selectors and emoji modifiers, and ZWJ sequences, flags and Hangul syllables are not split.
+    #[default]
+    Grapheme,
+}
+
+impl Default for ParserOptions<'_> {
     fn default() -> Self {
         Self {
             truncate_too_long_string: true,
+            truncation: TruncationMode::default(),
+            fields: FieldMask::ALL,
+            lenient: false,
+            diagnostics: None,
+            unknown_keys: None,
+            violations: None,
+            unmodeled_keys: REMO_API_UNMODELED_KEYS,
+        }
+    }
+}
+
+impl<'a> ParserOptions<'a> {
+    /// Boundary to truncate too long strings at. `TruncationMode::Char` is the behavior of the former versions.
+    pub fn with_truncation(mut self, truncation: TruncationMode) -> Self {
+        self.truncation = truncation;
+        self
+    }
+
+    pub fn truncation(&self) -> TruncationMode {
+        self.truncation
+    }
+
+    /// Parse only the values whose keys are in `fields`. Other values are skipped without conversion nor validation,
+    /// and the fields of the models keep their default values.
+    ///
+    /// Maps and arrays whose keys are not selected are skipped entirely, e.g. `te` of `newest_events`
+    /// or `device` of an appliance, except `users` of `read_devices` which is always parsed
+    /// because the device callback is invoked at its beginning.
+    /// Note that a key selects the values in all maps, e.g. `Name` selects the names of both devices and models.
+    pub fn with_fields(mut self, fields: FieldMask) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    pub fn fields(&self) -> FieldMask {
+        self.fields
+    }
+
+    pub(crate) fn is_selected(&self, key: ModelNodeKey) -> bool {
+        self.fields.contains(key)
+    }
+
+    /// Enable lenient mode. A field which fails to parse, e.g. a malformed timestamp or MAC address,
+    /// is reported to `diagnostics` and set to its default value instead of aborting the parser.
+    /// Errors of the document structure still abort the parser.
+    pub fn lenient(mut self, diagnostics: &'a dyn DiagnosticsSink) -> Self {
+        self.lenient = true;
+        self.diagnostics = Some(diagnostics);
+        self
+    }
+
+    pub fn is_lenient(&self) -> bool {
+        self.lenient
+    }
+
+    /// Report truncated strings to `diagnostics` without enabling lenient mode.
+    pub fn with_diagnostics(mut self, diagnostics: &'a dyn DiagnosticsSink) -> Self {
+        self.diagnostics = Some(diagnostics);
+        self
+    }
+
+    /// Report the keys not known by the parsers to `unknown_keys`, to detect the fields added to the API.
+    /// Keys of the API which the parsers do not model are reported as `UnknownKeyKind::Unmodeled`.
+    /// Keys in maps and arrays skipped as unknown are not reported.
+    pub fn with_unknown_keys(mut self, unknown_keys: &'a dyn UnknownKeySink) -> Self {
+        self.unknown_keys = Some(unknown_keys);
+        self
+    }
+
+    /// Check the document against the schema of the Remo API, for conformance tests.
+    /// Missing required fields, values of wrong JSON types and unknown keys are reported to `violations`,
+    /// and the `read_*` functions fail with `ModelNodeParseError::SchemaViolation` after parsing the whole document.
+    ///
+    /// Only the selected fields are checked for their types. Keys in maps and arrays skipped as unknown are not checked.
+    pub fn strict(mut self, violations: &'a dyn ViolationSink) -> Self {
+        self.violations = Some(violations);
+        self
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.violations.is_some()
+    }
+
+    /// Keys of devices and appliances which are in the API but not modeled by the parsers, `REMO_API_UNMODELED_KEYS` by default.
+    /// They are not unknown keys in strict mode, but are still reported to the sink of `with_unknown_keys`
+    /// as `UnknownKeyKind::Unmodeled`. Pass `&[]` to check them as unknown keys.
+    pub fn with_unmodeled_keys(mut self, unmodeled_keys: &'a [&'a str]) -> Self {
+        self.unmodeled_keys = unmodeled_keys;
+        self
+    }
+
+    pub fn unmodeled_keys(&self) -> &'a [&'a str] {
+        self.unmodeled_keys
+    }
+
+    /// Report an unknown `key` of the map at `path`, checking it in strict mode unless it is unmodeled.
+    /// `unmodeled` is false for the maps other than devices and appliances.
+    pub(crate) fn report_unknown_key(&self, path: &PathTracker, key: &str, unmodeled: bool, count: &mut usize) {
+        let kind = if unmodeled && self.unmodeled_keys.contains(&key) { UnknownKeyKind::Unmodeled } else { UnknownKeyKind::Unknown };
+        if let Some(unknown_keys) = self.unknown_keys {
+            unknown_keys.report(path.container_path(), key, kind);
+        }
+        if kind == UnknownKeyKind::Unknown {
+            self.report_violation(ViolationKind::UnknownKey, path.path(), count);
+        }
+    }
+
+    /// Report a violation at `path` in strict mode, counting it in `count`.
+    pub(crate) fn report_violation(&self, kind: ViolationKind, path: &[PathSegment], count: &mut usize) {
+        if let Some(violations) = self.violations {
+            *count += 1;
+            violations.report(SchemaViolation {
+                kind,
+                path: path.iter().copied().take(MAX_ERROR_PATH_DEPTH).collect(),
+            });
+        }
+    }
+
+    /// Report a numeric field which is not a JSON number in strict mode, even if it can be coerced.
+    pub(crate) fn check_number(&self, key: ModelNodeKey, value: &JsonScalarValue, path: &[PathSegment], count: &mut usize) {
+        if !matches!(value, JsonScalarValue::Number(_)) {
+            self.report_violation(ViolationKind::WrongType(key), path, count);
+        }
+    }
+
+    /// Report the `required` fields not in `seen` of the map at `path` in strict mode.
+    pub(crate) fn check_required(&self, required: &[ModelNodeKey], seen: FieldMask, path: &[PathSegment], count: &mut usize) {
+        if self.violations.is_none() {
+            return;
+        }
+        for key in required.iter().filter(|key| !seen.contains(**key)) {
+            let mut field_path: heapless::Vec<PathSegment, MAX_ERROR_PATH_DEPTH> = path.iter().copied().take(MAX_ERROR_PATH_DEPTH).collect();
+            field_path.push(PathSegment::Key(*key)).ok();
+            self.report_violation(ViolationKind::MissingField(*key), &field_path, count);
+        }
+    }
+
+
+    fn report(&self, key: ModelNodeKey, index: usize, kind: DiagnosticKind) {
+        if let Some(diagnostics) = self.diagnostics {
+            diagnostics.report(FieldDiagnostic { key, index, kind });
+        }
+    }
+
+    /// Result of a field conversion. In lenient mode, an error is reported and replaced with the default value.
+    pub(crate) fn recover_field<T: Default, E: Into<ModelNodeParseError>>(
+        &self,
+        key: ModelNodeKey,
+        index: usize,
+        result: Result<T, E>,
+    ) -> Result<T, ModelNodeParseError> {
+        self.recover_field_or(key, index, result, T::default())
+    }
+
+    /// Same as `recover_field`, but an error is replaced with `fallback`.
+    pub(crate) fn recover_field_or<T, E: Into<ModelNodeParseError>>(
+        &self,
+        key: ModelNodeKey,
+        index: usize,
+        result: Result<T, E>,
+        fallback: T,
+    ) -> Result<T, ModelNodeParseError> {
+        match result {
+            Ok(value) => Ok(value),
+            Err(error) if self.lenient => {
+                self.report(key, index, DiagnosticKind::Invalid(error.into()));
+                Ok(fallback)
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Copy a string field, reporting and counting its truncation.
+    pub(crate) fn copy_field<T: ModelString>(&self, key: ModelNodeKey, index: usize, s: &str, statistics: &mut ReadStatistics) -> Result<T, ModelNodeParseError> {
+        let copied = T::copy_from(s, self);
+        // A string recovered to the default has been reported as invalid, not truncated.
+        let truncated = matches!(&copied, Ok(string) if string.len() < s.len());
+        let string = self.recover_field(key, index, copied)?;
+        if truncated {
+            statistics.truncated_strings += 1;
+            self.report(key, index, DiagnosticKind::Truncated { length: s.len() });
+        }
+        Ok(string)
+    }
+
+    /// Copy a piece of a string field which may be longer than the parser buffer, appending it to the preceding pieces.
+    /// The truncation is reported and counted at the last piece.
+    pub(crate) fn copy_piece<T: ModelString>(&self, field: &mut T, key: ModelNodeKey, index: usize, value: JsonScalarValue, statistics: &mut ReadStatistics) -> Result<(), ModelNodeParseError> {
+        let (s, offset, terminated) = match value {
+            JsonScalarValue::String(s) => {
+                *field = self.copy_field(key, index, s, statistics)?;
+                return Ok(());
+            }
+            JsonScalarValue::ContinuingString(s, offset, terminated) => (s, offset, terminated),
+            _ => return Ok(()),
+        };
+        if offset == 0 {
+            *field = self.recover_field(key, index, T::copy_from(s, self))?;
+        } else if field.len() == offset {
+            // Pieces following a truncated or recovered one are dropped.
+            if let Err(error) = field.append(s, self) {
+                *field = self.recover_field(key, index, Err::<T, _>(error))?;
+            }
+        }
+        let length = offset + s.len();
+        if terminated && self.truncate_too_long_string && field.len() < length {
+            statistics.truncated_strings += 1;
+            self.report(key, index, DiagnosticKind::Truncated { length });
+        }
+        Ok(())
+    }
+
+    /// Take the key of `value`, keeping it for the following pieces of a string longer than the parser buffer.
+    pub(crate) fn take_key(&self, node_key: &mut Option<ModelNodeKey>, value: &JsonScalarValue) -> Option<ModelNodeKey> {
+        let key = if matches!(value, JsonScalarValue::ContinuingString(_, _, false)) { *node_key } else { node_key.take() };
+        key.filter(|key| self.is_selected(*key))
+    }
+}
+
+/// Whether `value` is a string, including a piece of a string longer than the parser buffer.
+pub(crate) fn is_string(value: &JsonScalarValue) -> bool {
+    matches!(value, JsonScalarValue::String(_) | JsonScalarValue::ContinuingString(..))
+}
+
+/// Whether `head` followed by `tail` can be cut at the character boundary `index` without splitting a grapheme cluster.
+fn is_grapheme_boundary(head: &str, tail: &str, index: usize) -> bool {
+    let mut cursor = GraphemeCursor::new(index, head.len() + tail.len(), true);
+    let (chunk, chunk_start) = if index < head.len() { (head, 0) } else { (tail, head.len()) };
+    loop {
+        match cursor.is_boundary(chunk, chunk_start) {
+            Ok(is_boundary) => return is_boundary,
+            // The preceding characters are all in `head`.
+            Err(GraphemeIncomplete::PreContext(end)) => cursor.provide_context(&head[..end], 0),
+            Err(_) => return true,
         }
     }
 }
 
-/// Copy string as long as the storage can hold.
-pub fn copy_string_possible<const N: usize>(s: &str) -> String<N> {
-    let mut string = String::new();
-    for c in s.chars() {
-        if let Err(_) = string.push(c) {
-            break;
+/// The longest prefix of `s` within `capacity` bytes, cut at a boundary of `mode`.
+pub fn truncate_str(s: &str, capacity: usize, mode: TruncationMode) -> &str {
+    if s.len() <= capacity {
+        return s;
+    }
+    let mut index = capacity;
+    while !s.is_char_boundary(index) {
+        index -= 1;
+    }
+    if mode == TruncationMode::Grapheme {
+        while index > 0 && !is_grapheme_boundary(s, "", index) {
+            index = s[..index].char_indices().next_back().map_or(0, |(i, _)| i);
         }
     }
-    string
+    &s[..index]
+}
+
+/// Copy string as long as the storage can hold, cutting at a boundary of `mode`.
+pub fn copy_string_possible<const N: usize>(s: &str, mode: TruncationMode) -> String<N> {
+    String::from(truncate_str(s, N, mode))
 }
 
 /// Copy string. Truncate extra characters which cannot be held by the storage if options.truncate_too_long_string is true.
 pub fn copy_string_option<const N: usize>(s: &str, options: &ParserOptions) -> Result<String<N>, ModelNodeParseError> {
     if options.truncate_too_long_string {
-        Ok(copy_string_possible(s))
+        Ok(copy_string_possible(s, options.truncation))
     } else {
         String::from_str(s).map_err(|_| ModelNodeParseError::StringTooLong)
     }
-}
\ No newline at end of file
+}
+
+/// Append `s` to `string` as long as the storage can hold, cutting at a boundary of `mode`.
+/// The boundary is checked across the end of `string`, so if `s` continues the last grapheme cluster of a full `string`,
+/// e.g. with a combining mark, the cluster is removed from `string`.
+fn append_string_possible<const N: usize>(string: &mut String<N>, s: &str, mode: TruncationMode) {
+    let length = string.len();
+    let mut cut = length + truncate_str(s, N - length, TruncationMode::Char).len();
+    if cut < length + s.len() && mode == TruncationMode::Grapheme {
+        while cut > 0 && !is_grapheme_boundary(string, s, cut) {
+            let previous = if cut > length { s[..cut - length].chars().next_back() } else { string[..cut].chars().next_back() };
+            cut -= previous.map_or(0, char::len_utf8);
+        }
+    }
+    if cut < length {
+        string.truncate(cut);
+    } else {
+        string.push_str(&s[..cut - length]).ok();
+    }
+}
+
+/// Append string to `string`. Truncate extra characters which cannot be held by the storage if options.truncate_too_long_string is true.
+pub fn append_string_option<const N: usize>(string: &mut String<N>, s: &str, options: &ParserOptions) -> Result<(), ModelNodeParseError> {
+    if options.truncate_too_long_string {
+        append_string_possible(string, s, options.truncation);
+        Ok(())
+    } else {
+        string.push_str(s).map_err(|_| ModelNodeParseError::StringTooLong)
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::diagnostics::Diagnostics;
+
+    #[test]
+    fn test_truncate_str() {
+        // "ボ" as a base character and a combining voiced sound mark.
+        let s = "ホ\u{3099}ン";
+        assert_eq!(truncate_str(s, 6, TruncationMode::Char), "ホ\u{3099}");
+        assert_eq!(truncate_str(s, 5, TruncationMode::Char), "ホ");
+        assert_eq!(truncate_str(s, 5, TruncationMode::Grapheme), "");
+        assert_eq!(truncate_str(s, 6, TruncationMode::Grapheme), "ホ\u{3099}");
+
+        // Family emoji joined with ZWJ, and a pair of regional indicators.
+        let s = "a\u{1F468}\u{200D}\u{1F469}b";
+        assert_eq!(truncate_str(s, 8, TruncationMode::Char), "a\u{1F468}\u{200D}");
+        assert_eq!(truncate_str(s, 8, TruncationMode::Grapheme), "a");
+        let s = "\u{1F1EF}\u{1F1F5}\u{1F1EF}\u{1F1F5}";
+        assert_eq!(truncate_str(s, 12, TruncationMode::Grapheme), "\u{1F1EF}\u{1F1F5}");
+
+        // Halfwidth katakana "ﾎﾞ" with the halfwidth voiced sound mark, and Hangul jamo of one syllable.
+        let s = "ｱ\u{FF8E}\u{FF9E}";
+        assert_eq!(truncate_str(s, 6, TruncationMode::Char), "ｱ\u{FF8E}");
+        assert_eq!(truncate_str(s, 6, TruncationMode::Grapheme), "ｱ");
+        assert_eq!(truncate_str("\u{1100}\u{1161}\u{11A8}", 6, TruncationMode::Grapheme), "");
+
+        let options = ParserOptions::default();
+        assert_eq!(copy_string_option::<4>("ab\u{00E9}", &options).unwrap().as_str(), "ab\u{00E9}");
+        assert_eq!(copy_string_option::<3>("abe\u{0301}", &options).unwrap().as_str(), "ab");
+        let options = options.with_truncation(TruncationMode::Char);
+        assert_eq!(copy_string_option::<3>("abe\u{0301}", &options).unwrap().as_str(), "abe");
+    }
+
+    #[test]
+    fn test_copy_field_reports() {
+        let diagnostics = Diagnostics::<4>::new();
+        let mut statistics = ReadStatistics::default();
+        let options = ParserOptions::default().with_diagnostics(&diagnostics);
+        let string: String<3> = options.copy_field(ModelNodeKey::Name, 0, "abcd", &mut statistics).unwrap();
+        assert_eq!(string.as_str(), "abc");
+        assert_eq!(statistics.truncated_strings, 1);
+        assert_eq!(diagnostics.take().as_slice(), &[FieldDiagnostic { key: ModelNodeKey::Name, index: 0, kind: DiagnosticKind::Truncated { length: 4 } }]);
+
+        // A string recovered to the default is reported only as invalid.
+        let mut statistics = ReadStatistics::default();
+        let mut options = ParserOptions::default().lenient(&diagnostics);
+        options.truncate_too_long_string = false;
+        let string: String<3> = options.copy_field(ModelNodeKey::Name, 1, "abcd", &mut statistics).unwrap();
+        assert_eq!(string.as_str(), "");
+        assert_eq!(statistics.truncated_strings, 0);
+        assert_eq!(diagnostics.take().as_slice(), &[FieldDiagnostic { key: ModelNodeKey::Name, index: 1, kind: DiagnosticKind::Invalid(ModelNodeParseError::StringTooLong) }]);
+    }
+
+    #[test]
+    fn test_copy_piece_reports() {
+        let pieces = [
+            JsonScalarValue::ContinuingString("abc", 0, false),
+            JsonScalarValue::ContinuingString("de", 3, false),
+            JsonScalarValue::ContinuingString("fgh", 5, true),
+        ];
+        let diagnostics = Diagnostics::<4>::new();
+        let mut statistics = ReadStatistics::default();
+        let options = ParserOptions::default().with_diagnostics(&diagnostics);
+        let mut string = String::<6>::new();
+        for piece in pieces {
+            options.copy_piece(&mut string, ModelNodeKey::Name, 0, piece, &mut statistics).unwrap();
+        }
+        assert_eq!(string.as_str(), "abcdef");
+        assert_eq!(statistics.truncated_strings, 1);
+        assert_eq!(diagnostics.take().as_slice(), &[FieldDiagnostic { key: ModelNodeKey::Name, index: 0, kind: DiagnosticKind::Truncated { length: 8 } }]);
+
+        // The pieces following the recovered one are dropped.
+        let mut statistics = ReadStatistics::default();
+        let mut options = ParserOptions::default().lenient(&diagnostics);
+        options.truncate_too_long_string = false;
+        let mut string = String::<6>::new();
+        for piece in pieces {
+            options.copy_piece(&mut string, ModelNodeKey::Name, 1, piece, &mut statistics).unwrap();
+        }
+        assert_eq!(string.as_str(), "");
+        assert_eq!(statistics.truncated_strings, 0);
+        assert_eq!(diagnostics.take().as_slice(), &[FieldDiagnostic { key: ModelNodeKey::Name, index: 1, kind: DiagnosticKind::Invalid(ModelNodeParseError::StringTooLong) }]);
+    }
+
+    #[test]
+    fn test_copy_piece_grapheme() {
+        // The first piece fills the field, and the second piece starts with a combining mark of its last character.
+        let pieces = [
+            JsonScalarValue::ContinuingString("abcホ", 0, false),
+            JsonScalarValue::ContinuingString("\u{3099}ン", 6, true),
+        ];
+        let diagnostics = Diagnostics::<4>::new();
+        let mut statistics = ReadStatistics::default();
+        let options = ParserOptions::default().with_diagnostics(&diagnostics);
+        let mut string = String::<6>::new();
+        for piece in pieces {
+            options.copy_piece(&mut string, ModelNodeKey::Name, 0, piece, &mut statistics).unwrap();
+        }
+        assert_eq!(string.as_str(), "abc");
+        assert_eq!(diagnostics.take().as_slice(), &[FieldDiagnostic { key: ModelNodeKey::Name, index: 0, kind: DiagnosticKind::Truncated { length: 12 } }]);
+
+        let options = options.with_truncation(TruncationMode::Char);
+        let mut string = String::<6>::new();
+        for piece in pieces {
+            options.copy_piece(&mut string, ModelNodeKey::Name, 0, piece, &mut statistics).unwrap();
+        }
+        assert_eq!(string.as_str(), "abcホ");
+
+        let mut string = String::<6>::from("ｱ\u{FF8E}");
+        append_string_option(&mut string, "\u{FF9E}", &ParserOptions::default()).unwrap();
+        assert_eq!(string.as_str(), "ｱ");
+
+        // A ZWJ sequence split across the pieces, and a pair of regional indicators.
+        let mut string = String::<6>::from("ab\u{1F468}");
+        append_string_option(&mut string, "\u{200D}\u{1F469}", &ParserOptions::default()).unwrap();
+        assert_eq!(string.as_str(), "ab");
+        let mut string = String::<12>::from("\u{1F1EF}\u{1F1F5}\u{1F1EF}");
+        append_string_option(&mut string, "\u{1F1F5}", &ParserOptions::default()).unwrap();
+        assert_eq!(string.as_str(), "\u{1F1EF}\u{1F1F5}");
+    }
+}
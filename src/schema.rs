@@ -0,0 +1,117 @@
+// Schema violations reported in strict mode.
+// Copyright 2022-2023 Kenta Ida 
+// SPDX-License-Identifier: MIT
+//
+use core::fmt;
+
+use heapless::Vec;
+
+use crate::config::MAX_ERROR_PATH_DEPTH;
+use crate::diagnostics::BoundedSink;
+use crate::location::{write_path, PathSegment};
+use crate::node_key::{FieldMask, ModelNodeKey};
+
+/// Fields which a device map must have, in both `/1/devices` and `device` of an appliance.
+pub(crate) const REQUIRED_DEVICE_FIELDS: &[ModelNodeKey] = &[
+    ModelNodeKey::Id,
+    ModelNodeKey::Name,
+    ModelNodeKey::CreatedAt,
+    ModelNodeKey::UpdatedAt,
+    ModelNodeKey::MacAddress,
+    ModelNodeKey::SerialNumber,
+    ModelNodeKey::FirmwareVersion,
+    ModelNodeKey::TemperatureOffset,
+    ModelNodeKey::HumidityOffset,
+];
+pub(crate) const REQUIRED_USER_FIELDS: &[ModelNodeKey] = &[ModelNodeKey::Id, ModelNodeKey::NickName, ModelNodeKey::SuperUser];
+pub(crate) const REQUIRED_SENSOR_VALUE_FIELDS: &[ModelNodeKey] = &[ModelNodeKey::Val, ModelNodeKey::CreatedAt];
+pub(crate) const REQUIRED_APPLIANCE_FIELDS: &[ModelNodeKey] = &[
+    ModelNodeKey::Id,
+    ModelNodeKey::Type,
+    ModelNodeKey::NickName,
+    ModelNodeKey::Image,
+    ModelNodeKey::Device,
+];
+pub(crate) const REQUIRED_MODEL_FIELDS: &[ModelNodeKey] = &[ModelNodeKey::Id, ModelNodeKey::Name, ModelNodeKey::Manufacturer, ModelNodeKey::Image];
+pub(crate) const REQUIRED_PROPERTY_FIELDS: &[ModelNodeKey] = &[ModelNodeKey::Name, ModelNodeKey::Epc, ModelNodeKey::Val, ModelNodeKey::UpdatedAt];
+
+// Keys known in each map. A key known in another map, e.g. `nickname` in a device map, is an unknown key.
+pub(crate) const DEVICE_KEYS: FieldMask = FieldMask::from_keys(REQUIRED_DEVICE_FIELDS).with(ModelNodeKey::BtMacAddress);
+pub(crate) const USER_KEYS: FieldMask = FieldMask::from_keys(REQUIRED_USER_FIELDS);
+pub(crate) const NEWEST_EVENTS_KEYS: FieldMask =
+    FieldMask::from_keys(&[ModelNodeKey::Te, ModelNodeKey::Hu, ModelNodeKey::Il, ModelNodeKey::Mo]);
+pub(crate) const SENSOR_VALUE_KEYS: FieldMask = FieldMask::from_keys(REQUIRED_SENSOR_VALUE_FIELDS);
+pub(crate) const APPLIANCE_KEYS: FieldMask =
+    FieldMask::from_keys(REQUIRED_APPLIANCE_FIELDS).with(ModelNodeKey::Model).with(ModelNodeKey::SmartMeter);
+pub(crate) const MODEL_KEYS: FieldMask = FieldMask::from_keys(REQUIRED_MODEL_FIELDS)
+    .with(ModelNodeKey::Country)
+    .with(ModelNodeKey::RemoteName)
+    .with(ModelNodeKey::Series);
+pub(crate) const SMART_METER_KEYS: FieldMask = FieldMask::from_keys(&[ModelNodeKey::EchonetLiteProperties]);
+pub(crate) const PROPERTY_KEYS: FieldMask = FieldMask::from_keys(REQUIRED_PROPERTY_FIELDS);
+
+/// Keys of devices and appliances in the Remo API which the parsers do not model.
+/// The default of `ParserOptions::with_unmodeled_keys`.
+pub const REMO_API_UNMODELED_KEYS: &[&str] = &["commissionee_fabrics", "settings", "aircon", "signals", "tv", "light"];
+
+/// Kind of a schema violation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ViolationKind {
+    /// A required field is missing in a map.
+    MissingField(ModelNodeKey),
+    /// The value of a field has a JSON type the parser does not accept, e.g. `epc` as a string.
+    WrongType(ModelNodeKey),
+    /// A key not known by the parser.
+    UnknownKey,
+}
+
+/// A violation of the schema of the Remo API, and the JSON path to the field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchemaViolation {
+    pub kind: ViolationKind,
+    /// Path to the field, e.g. `[0].users[1].nickname`. Unknown keys end with `PathSegment::UnknownKey`.
+    pub path: Vec<PathSegment, MAX_ERROR_PATH_DEPTH>,
+}
+
+impl SchemaViolation {
+    /// Index of the device or the appliance in the document.
+    pub fn index(&self) -> Option<usize> {
+        match self.path.first() {
+            Some(PathSegment::Index(index)) => Some(*index),
+            _ => None,
+        }
+    }
+}
+
+/// Format as `<kind> at <path>`.
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            ViolationKind::MissingField(_) => f.write_str("missing field")?,
+            ViolationKind::WrongType(_) => f.write_str("wrong type")?,
+            ViolationKind::UnknownKey => f.write_str("unknown key")?,
+        }
+        f.write_str(" at ")?;
+        write_path(f, &self.path)
+    }
+}
+
+/// Receives the schema violations in strict mode. See `ParserOptions::strict`.
+pub trait ViolationSink {
+    fn report(&self, violation: SchemaViolation);
+}
+
+/// Ignores all violations, so strict mode only fails.
+impl ViolationSink for () {
+    fn report(&self, _violation: SchemaViolation) {}
+}
+
+/// Keeps the first `N` violations and counts the rest.
+pub type Violations<const N: usize> = BoundedSink<SchemaViolation, N>;
+
+impl<const N: usize> ViolationSink for Violations<N> {
+    fn report(&self, violation: SchemaViolation) {
+        self.push(violation);
+    }
+}
@@ -0,0 +1,240 @@
+// Sensor history store for NewestEvents.
+// Copyright 2022-2023 Kenta Ida 
+// SPDX-License-Identifier: MIT
+//
+use heapless::{HistoryBuffer, Vec};
+use uuid::Uuid;
+
+use crate::common_types::*;
+use crate::device::{NewestEvents, SensorValue};
+
+/// Kind of numeric sensor in `NewestEvents`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SensorKind {
+    Temperature,
+    Humidity,
+    Illumination,
+}
+
+/// Statistics of sensor values within a window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WindowStats {
+    pub count: usize,
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    /// Timestamp of the oldest value within the window.
+    pub first: Timestamp,
+    /// Timestamp of the newest value within the window.
+    pub last: Timestamp,
+}
+
+/// History of sensor values of a device. Holds `N` values for each sensor.
+pub struct DeviceHistory<const N: usize> {
+    id: Uuid,
+    temperature: HistoryBuffer<SensorValue, N>,
+    humidity: HistoryBuffer<SensorValue, N>,
+    illumination: HistoryBuffer<SensorValue, N>,
+}
+
+/// Store the value if it is newer than the recent one. Returns `true` if stored.
+fn store<const N: usize>(buffer: &mut HistoryBuffer<SensorValue, N>, value: &Option<SensorValue>) -> bool {
+    match value {
+        Some(value) if buffer.recent().map(|r| r.created_at < value.created_at).unwrap_or(true) => {
+            buffer.write(value.clone());
+            true
+        }
+        _ => false,
+    }
+}
+
+impl<const N: usize> DeviceHistory<N> {
+    fn new(id: Uuid) -> Self {
+        Self {
+            id,
+            temperature: HistoryBuffer::new(),
+            humidity: HistoryBuffer::new(),
+            illumination: HistoryBuffer::new(),
+        }
+    }
+
+    pub fn id(&self) -> &Uuid {
+        &self.id
+    }
+
+    /// Ingest sensor values. Values whose `created_at` is not newer than the recent one are ignored.
+    /// Returns the number of values stored.
+    pub fn ingest(&mut self, events: &NewestEvents) -> usize {
+        store(&mut self.temperature, &events.temperature) as usize
+            + store(&mut self.humidity, &events.humidity) as usize
+            + store(&mut self.illumination, &events.illumination) as usize
+    }
+
+    fn buffer(&self, kind: SensorKind) -> &HistoryBuffer<SensorValue, N> {
+        match kind {
+            SensorKind::Temperature => &self.temperature,
+            SensorKind::Humidity => &self.humidity,
+            SensorKind::Illumination => &self.illumination,
+        }
+    }
+
+    /// Values of the sensor from the oldest to the newest.
+    pub fn values(&self, kind: SensorKind) -> impl Iterator<Item = &SensorValue> {
+        self.buffer(kind).oldest_ordered()
+    }
+
+    /// The newest value of the sensor.
+    pub fn latest(&self, kind: SensorKind) -> Option<&SensorValue> {
+        self.buffer(kind).recent()
+    }
+
+    /// Statistics of the values created at or after `since`.
+    pub fn stats(&self, kind: SensorKind, since: Timestamp) -> Option<WindowStats> {
+        let mut stats: Option<WindowStats> = None;
+        let mut sum = 0.0f32;
+        for value in self.values(kind).filter(|v| v.created_at >= since) {
+            sum += value.val;
+            stats = Some(match stats {
+                None => WindowStats {
+                    count: 1,
+                    min: value.val,
+                    max: value.val,
+                    mean: 0.0,
+                    first: value.created_at,
+                    last: value.created_at,
+                },
+                Some(stats) => WindowStats {
+                    count: stats.count + 1,
+                    min: stats.min.min(value.val),
+                    max: stats.max.max(value.val),
+                    last: value.created_at,
+                    ..stats
+                },
+            });
+        }
+        stats.map(|stats| WindowStats {
+            mean: sum / stats.count as f32,
+            ..stats
+        })
+    }
+
+    /// Least-squares slope of the values created at or after `since` in units per hour.
+    /// Returns `None` if there are less than two values in the window.
+    pub fn slope_per_hour(&self, kind: SensorKind, since: Timestamp) -> Option<f32> {
+        let origin = self.values(kind).find(|v| v.created_at >= since)?.created_at;
+        let (mut n, mut sum_x, mut sum_y, mut sum_xx, mut sum_xy) = (0.0f32, 0.0f32, 0.0f32, 0.0f32, 0.0f32);
+        for value in self.values(kind).filter(|v| v.created_at >= since) {
+            let x = (value.created_at - origin).num_seconds() as f32 / 3600.0;
+            n += 1.0;
+            sum_x += x;
+            sum_y += value.val;
+            sum_xx += x * x;
+            sum_xy += x * value.val;
+        }
+        let denominator = n * sum_xx - sum_x * sum_x;
+        if n < 2.0 || denominator == 0.0 {
+            return None;
+        }
+        Some((n * sum_xy - sum_x * sum_y) / denominator)
+    }
+}
+
+/// History of sensor values of up to `DEVICES` devices keyed by the device ID, holding `N` values for each sensor.
+pub struct SensorHistory<const DEVICES: usize, const N: usize> {
+    devices: Vec<DeviceHistory<N>, DEVICES>,
+}
+
+impl<const DEVICES: usize, const N: usize> Default for SensorHistory<DEVICES, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const DEVICES: usize, const N: usize> SensorHistory<DEVICES, N> {
+    pub const fn new() -> Self {
+        Self { devices: Vec::new() }
+    }
+
+    /// Ingest sensor values of the device.
+    /// Returns the number of values stored, or `None` if there is no room for a new device.
+    pub fn ingest(&mut self, id: &Uuid, events: &NewestEvents) -> Option<usize> {
+        if let Some(device) = self.devices.iter_mut().find(|d| d.id == *id) {
+            return Some(device.ingest(events));
+        }
+        self.devices.push(DeviceHistory::new(*id)).ok()?;
+        self.devices.last_mut().map(|device| device.ingest(events))
+    }
+
+    /// History of the device.
+    pub fn device(&self, id: &Uuid) -> Option<&DeviceHistory<N>> {
+        self.devices.iter().find(|d| d.id == *id)
+    }
+
+    /// Histories of all devices.
+    pub fn devices(&self) -> impl Iterator<Item = &DeviceHistory<N>> {
+        self.devices.iter()
+    }
+
+    /// Remove the history of the device.
+    pub fn remove(&mut self, id: &Uuid) {
+        if let Some(index) = self.devices.iter().position(|d| d.id == *id) {
+            self.devices.swap_remove(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::str::FromStr;
+    use uuid::uuid;
+
+    use super::*;
+
+    fn events(t: &str, temperature: f32, humidity: f32) -> NewestEvents {
+        let created_at = Timestamp::from_str(t).unwrap();
+        NewestEvents {
+            temperature: Some(SensorValue { val: temperature, created_at }),
+            humidity: Some(SensorValue { val: humidity, created_at }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sensor_history() {
+        let id = uuid!("f262cb0c-a853-47bb-9559-44d0f2c4d6e2");
+        let mut history = SensorHistory::<2, 4>::new();
+        assert_eq!(history.ingest(&id, &events("2022-10-20T22:00:00Z", 20.0, 50.0)), Some(2));
+        assert_eq!(history.ingest(&id, &events("2022-10-20T22:00:00Z", 20.0, 50.0)), Some(0));
+        assert_eq!(history.ingest(&id, &events("2022-10-20T22:30:00Z", 20.6, 52.0)), Some(2));
+        assert_eq!(history.ingest(&id, &events("2022-10-20T23:00:00Z", 21.2, 54.0)), Some(2));
+
+        let device = history.device(&id).unwrap();
+        let since = Timestamp::from_str("2022-10-20T22:00:00Z").unwrap();
+        let stats = device.stats(SensorKind::Temperature, since).unwrap();
+        assert_eq!(stats.count, 3);
+        assert_eq!(stats.min, 20.0);
+        assert_eq!(stats.max, 21.2);
+        assert!((stats.mean - 20.6).abs() < 1e-4);
+        assert_eq!(stats.last, Timestamp::from_str("2022-10-20T23:00:00Z").unwrap());
+        let slope = device.slope_per_hour(SensorKind::Temperature, since).unwrap();
+        assert!((slope - 1.2).abs() < 1e-4);
+        let slope = device.slope_per_hour(SensorKind::Humidity, since).unwrap();
+        assert!((slope - 4.0).abs() < 1e-4);
+        assert!(device.stats(SensorKind::Illumination, since).is_none());
+        let since = Timestamp::from_str("2022-10-20T22:45:00Z").unwrap();
+        assert!(device.slope_per_hour(SensorKind::Temperature, since).is_none());
+    }
+
+    #[test]
+    fn test_sensor_history_capacity() {
+        let mut history = SensorHistory::<1, 2>::new();
+        let id = uuid!("f262cb0c-a853-47bb-9559-44d0f2c4d6e2");
+        let other = uuid!("12948215-568a-49ca-be45-c556e8140c56");
+        history.ingest(&id, &events("2022-10-20T22:00:00Z", 20.0, 50.0));
+        history.ingest(&id, &events("2022-10-20T22:30:00Z", 21.0, 50.0));
+        history.ingest(&id, &events("2022-10-20T23:00:00Z", 22.0, 50.0));
+        assert_eq!(history.ingest(&other, &events("2022-10-20T23:00:00Z", 22.0, 50.0)), None);
+        let values: Vec<f32, 2> = history.device(&id).unwrap().values(SensorKind::Temperature).map(|v| v.val).collect();
+        assert_eq!(values.as_slice(), &[21.0, 22.0]);
+    }
+}
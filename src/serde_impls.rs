@@ -0,0 +1,120 @@
+// Serde support for types which are not derived.
+// Copyright 2022-2023 Kenta Ida 
+// SPDX-License-Identifier: MIT
+//
+use core::{fmt, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::appliances::ApplianceType;
+use crate::device::MacAddress;
+
+/// Serialized as the type name in Remo Cloud API, e.g. `EL_SMART_METER`.
+impl Serialize for ApplianceType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ApplianceType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = ApplianceType;
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an appliance type")
+            }
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                match v {
+                    // Only round-trips the serialized `Unknown`, as the parser does not accept it.
+                    "UNKNOWN" => Ok(ApplianceType::Unknown),
+                    v => ApplianceType::try_from(v).map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self)),
+                }
+            }
+        }
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
+/// Serialized as a string in the colon style, e.g. `aa:bb:cc:dd:ee:ff`.
+impl Serialize for MacAddress {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for MacAddress {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = MacAddress;
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a MAC address")
+            }
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                MacAddress::from_str(v).map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self))
+            }
+        }
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use core::str::FromStr;
+    use heapless::String;
+    use uuid::uuid;
+
+    use crate::*;
+
+    #[test]
+    fn test_device_round_trip() {
+        let device = Device {
+            id: uuid!("8afdef94-43f7-4a16-b499-fbb6286f7438"),
+            name: String::from("Remo mini"),
+            temperature_offset: -0.5,
+            humidity_offset: 1.5,
+            created_at: Timestamp::from_str("2020-01-01T01:23:45Z").unwrap(),
+            updated_at: Timestamp::from_str("2020-01-01T01:23:45Z").unwrap(),
+            firmware_version: String::from("Remo-mini/1.10.0"),
+            mac_address: MacAddress::from_str("e8:db:84:00:11:22").unwrap(),
+            bt_mac_address: MacAddress::from_str("e8:db:84:22:33:44").unwrap(),
+            serial_number: String::from("2B012345678901"),
+        };
+        let json = serde_json::to_string(&device).unwrap();
+        assert_eq!(
+            json,
+            r#"{"id":"8afdef94-43f7-4a16-b499-fbb6286f7438","name":"Remo mini","temperature_offset":-0.5,"humidity_offset":1.5,"created_at":"2020-01-01T01:23:45Z","updated_at":"2020-01-01T01:23:45Z","firmware_version":"Remo-mini/1.10.0","mac_address":"e8:db:84:00:11:22","bt_mac_address":"e8:db:84:22:33:44","serial_number":"2B012345678901"}"#
+        );
+        assert_eq!(serde_json::from_str::<Device>(&json).unwrap(), device);
+    }
+
+    #[test]
+    fn test_newest_events_field_names() {
+        let events: NewestEvents =
+            serde_json::from_str(r#"{"te":{"val":24.8,"created_at":"2022-10-20T22:00:00Z"},"mo":{"val":1,"created_at":"2022-10-20T21:00:00Z"}}"#).unwrap();
+        assert_eq!(events.temperature.as_ref().unwrap().val, 24.8);
+        assert!(events.humidity.is_none());
+        assert_eq!(
+            serde_json::to_string(&events).unwrap(),
+            r#"{"te":{"val":24.8,"created_at":"2022-10-20T22:00:00Z"},"mo":{"val":1.0,"created_at":"2022-10-20T21:00:00Z"}}"#
+        );
+    }
+
+    #[test]
+    fn test_appliance_round_trip() {
+        let json = r#"{"id":"2f1ec1e4-5c4d-4bb3-9f54-a6fc51ae1b5e","type":"EL_SMART_METER","nickname":"スマートメーター","image":"ico_smartmeter"}"#;
+        let appliance: Appliance = serde_json::from_str(json).unwrap();
+        assert_eq!(appliance.type_, ApplianceType::SmartMeter);
+        assert_eq!(serde_json::to_string(&appliance).unwrap(), json);
+        assert!(serde_json::from_str::<ApplianceType>(r#""NEW_APPLIANCE""#).is_err());
+        // The type recovered in lenient mode round-trips.
+        assert_eq!(serde_json::to_string(&ApplianceType::Unknown).unwrap(), r#""UNKNOWN""#);
+        assert_eq!(serde_json::from_str::<ApplianceType>(r#""UNKNOWN""#).unwrap(), ApplianceType::Unknown);
+
+        let model: Model = serde_json::from_str(r#"{"id":"2f1ec1e4-5c4d-4bb3-9f54-a6fc51ae1b5e","country":"JP","manufacturer":"Panasonic","remote_name":"","series":"","name":"","image":""}"#).unwrap();
+        assert_eq!(model.manifacturer.as_str(), "Panasonic");
+    }
+}
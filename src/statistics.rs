@@ -0,0 +1,25 @@
+// Statistics of reading a JSON document.
+// Copyright 2022-2023 Kenta Ida 
+// SPDX-License-Identifier: MIT
+//
+
+/// Summary of a `read_devices` or `read_appliances` call, to size the capacities in `config`
+/// and to spot changes of the API schema.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ReadStatistics {
+    /// Devices or appliances in the document.
+    pub elements: usize,
+    /// Sub nodes passed to the callback.
+    pub sub_nodes: usize,
+    /// Keys not known or not modeled by the parser, except the ones in skipped maps and arrays.
+    pub unknown_keys: usize,
+    /// Maps and arrays skipped as their keys are unknown or not selected. Nested ones are not counted.
+    pub skipped_containers: usize,
+    /// Bytes read from the stream.
+    pub bytes_read: usize,
+    /// Maximum nesting depth of maps and arrays. The top level array is at depth 1.
+    pub max_depth: usize,
+    /// Strings truncated to fit their storage.
+    pub truncated_strings: usize,
+}
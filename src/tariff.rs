@@ -0,0 +1,295 @@
+// Electricity cost calculator on top of smart meter readings.
+// Copyright 2022-2023 Kenta Ida 
+// SPDX-License-Identifier: MIT
+//
+use crate::energy_aggregator::{energy_since, split_by_period, SampleResult, SmartMeterReading, SECONDS_PER_HOUR};
+
+/// Amount of money in 0.01 yen.
+pub type CentiYen = i64;
+
+const MWH_PER_KWH: i128 = 1_000_000;
+
+/// A tier of the tiered energy rate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateTier {
+    /// Upper bound of the tier in kWh per billing period. `None` for the last tier.
+    pub up_to_kwh: Option<u32>,
+    /// Rate in 0.01 yen per kWh.
+    pub rate: CentiYen,
+}
+
+/// A band of the time-of-use energy rate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeOfUseBand {
+    /// Start hour of the band in local time, inclusive.
+    pub start_hour: u8,
+    /// End hour of the band in local time, exclusive. The band wraps around midnight if `end_hour <= start_hour`.
+    pub end_hour: u8,
+    /// Rate in 0.01 yen per kWh.
+    pub rate: CentiYen,
+}
+
+impl TimeOfUseBand {
+    fn contains(&self, hour: u8) -> bool {
+        if self.start_hour < self.end_hour {
+            self.start_hour <= hour && hour < self.end_hour
+        } else {
+            self.start_hour <= hour || hour < self.end_hour
+        }
+    }
+}
+
+/// Energy charge of a tariff.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnergyRate<'a> {
+    /// Single rate in 0.01 yen per kWh.
+    Flat(CentiYen),
+    /// Tiered rates ordered by the upper bound, e.g. the three-tier Japanese residential rates.
+    /// Energy above the bound of the last tier is charged by the last tier.
+    Tiered(&'a [RateTier]),
+    /// Rates by time of day. Hours not covered by any band are charged by the last band.
+    TimeOfUse(&'a [TimeOfUseBand]),
+}
+
+/// Tariff of electricity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tariff<'a> {
+    /// Basic charge per billing period in 0.01 yen.
+    pub basic_charge: CentiYen,
+    pub energy_rate: EnergyRate<'a>,
+    /// Fuel cost adjustment in 0.01 yen per kWh. May be negative.
+    pub fuel_adjustment: CentiYen,
+    /// Renewable energy power promotion surcharge in 0.01 yen per kWh.
+    pub renewable_surcharge: CentiYen,
+}
+
+/// Cost of a billing period. Each charge is rounded toward zero to 0.01 yen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CostBreakdown {
+    /// Energy consumed within the period in milliwatt-hours.
+    pub energy_mwh: u64,
+    pub basic_charge: CentiYen,
+    pub energy_charge: CentiYen,
+    pub fuel_adjustment: CentiYen,
+    pub renewable_surcharge: CentiYen,
+    pub total: CentiYen,
+}
+
+/// Accumulates energy consumption of a billing period from smart meter readings and computes its cost.
+/// Energy is kept per local hour of day in milliwatt-hours, so the cost is computed exactly with integers.
+pub struct CostCalculator {
+    utc_offset_seconds: i64,
+    last: Option<SmartMeterReading>,
+    energy_by_hour_mwh: [u64; 24],
+}
+
+fn charge(energy_mwh: u64, rate: CentiYen) -> i128 {
+    energy_mwh as i128 * rate as i128
+}
+
+impl CostCalculator {
+    /// Create a calculator. Time-of-use bands are evaluated in the local time at `utc_offset_seconds`.
+    pub fn new(utc_offset_seconds: i32) -> Self {
+        Self {
+            utc_offset_seconds: utc_offset_seconds as i64,
+            last: None,
+            energy_by_hour_mwh: [0; 24],
+        }
+    }
+
+    /// Feed a reading. The energy consumed since the previous reading is accumulated to the current period.
+    pub fn push(&mut self, reading: &SmartMeterReading) -> SampleResult {
+        let last = match self.last {
+            None => {
+                self.last = Some(*reading);
+                return SampleResult::First;
+            }
+            Some(last) => last,
+        };
+        let energy_mwh = match energy_since(&last, reading) {
+            Ok(energy_mwh) => energy_mwh,
+            Err(SampleResult::CounterReset) => {
+                self.last = Some(*reading);
+                return SampleResult::CounterReset;
+            }
+            Err(result) => return result,
+        };
+        self.last = Some(*reading);
+        let utc_offset_seconds = self.utc_offset_seconds;
+        let energy_by_hour_mwh = &mut self.energy_by_hour_mwh;
+        split_by_period(
+            SECONDS_PER_HOUR,
+            utc_offset_seconds,
+            last.updated_at.timestamp(),
+            reading.updated_at.timestamp(),
+            energy_mwh,
+            |start, energy_mwh| {
+                let hour = (start + utc_offset_seconds).rem_euclid(24 * SECONDS_PER_HOUR) / SECONDS_PER_HOUR;
+                energy_by_hour_mwh[hour as usize] += energy_mwh;
+            },
+        );
+        SampleResult::Accepted
+    }
+
+    /// Start a new billing period. The last reading is kept to continue accumulation.
+    pub fn reset_period(&mut self) {
+        self.energy_by_hour_mwh = [0; 24];
+    }
+
+    /// Energy consumed within the current period in milliwatt-hours.
+    pub fn energy_mwh(&self) -> u64 {
+        self.energy_by_hour_mwh.iter().sum()
+    }
+
+    /// Compute the cost of the current period.
+    pub fn cost(&self, tariff: &Tariff) -> CostBreakdown {
+        let energy_mwh = self.energy_mwh();
+        let energy_charge = match tariff.energy_rate {
+            EnergyRate::Flat(rate) => charge(energy_mwh, rate),
+            EnergyRate::Tiered(tiers) => {
+                let mut remaining = energy_mwh;
+                let mut lower = 0u64;
+                let mut total = 0i128;
+                for tier in tiers {
+                    let upper = tier.up_to_kwh.map(|kwh| kwh as u64 * MWH_PER_KWH as u64);
+                    let in_tier = match upper {
+                        Some(upper) => remaining.min(upper.saturating_sub(lower)),
+                        None => remaining,
+                    };
+                    total += charge(in_tier, tier.rate);
+                    remaining -= in_tier;
+                    lower = upper.unwrap_or(u64::MAX);
+                    if remaining == 0 {
+                        break;
+                    }
+                }
+                total + tiers.last().map_or(0, |tier| charge(remaining, tier.rate))
+            }
+            EnergyRate::TimeOfUse(bands) => self
+                .energy_by_hour_mwh
+                .iter()
+                .enumerate()
+                .map(|(hour, energy_mwh)| {
+                    let band = bands.iter().find(|b| b.contains(hour as u8)).or(bands.last());
+                    charge(*energy_mwh, band.map_or(0, |b| b.rate))
+                })
+                .sum(),
+        };
+        let energy_charge = (energy_charge / MWH_PER_KWH) as CentiYen;
+        let fuel_adjustment = (charge(energy_mwh, tariff.fuel_adjustment) / MWH_PER_KWH) as CentiYen;
+        let renewable_surcharge = (charge(energy_mwh, tariff.renewable_surcharge) / MWH_PER_KWH) as CentiYen;
+        CostBreakdown {
+            energy_mwh,
+            basic_charge: tariff.basic_charge,
+            energy_charge,
+            fuel_adjustment,
+            renewable_surcharge,
+            total: tariff.basic_charge + energy_charge + fuel_adjustment + renewable_surcharge,
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::inconsistent_digit_grouping)] // Amounts are written as yen_sen.
+mod test {
+    use core::str::FromStr;
+
+    use super::*;
+    use crate::common_types::Timestamp;
+
+    const JST: i32 = 9 * 3600;
+
+    fn reading(t: &str, cumulative_energy_kwh: u64) -> SmartMeterReading {
+        SmartMeterReading {
+            updated_at: Timestamp::from_str(t).unwrap(),
+            cumulative_energy_mwh: cumulative_energy_kwh * 1_000_000,
+            instantaneous_power: None,
+            modulus_mwh: None,
+        }
+    }
+
+    #[test]
+    fn test_tiered_cost() {
+        const TIERS: &[RateTier] = &[
+            RateTier { up_to_kwh: Some(120), rate: 29_80 },
+            RateTier { up_to_kwh: Some(300), rate: 36_40 },
+            RateTier { up_to_kwh: None, rate: 40_49 },
+        ];
+        let tariff = Tariff {
+            basic_charge: 885_72,
+            energy_rate: EnergyRate::Tiered(TIERS),
+            fuel_adjustment: -9_14,
+            renewable_surcharge: 3_45,
+        };
+        let mut calculator = CostCalculator::new(JST);
+        assert_eq!(calculator.push(&reading("2022-10-01T00:00:00Z", 1000)), SampleResult::First);
+        assert_eq!(calculator.push(&reading("2022-10-31T00:00:00Z", 1350)), SampleResult::Accepted);
+        let cost = calculator.cost(&tariff);
+        assert_eq!(cost.energy_mwh, 350_000_000);
+        assert_eq!(cost.energy_charge, 12152_50);
+        assert_eq!(cost.fuel_adjustment, -3199_00);
+        assert_eq!(cost.renewable_surcharge, 1207_50);
+        assert_eq!(cost.total, 885_72 + 12152_50 - 3199_00 + 1207_50);
+
+        calculator.reset_period();
+        assert_eq!(calculator.cost(&tariff).total, 885_72);
+    }
+
+    #[test]
+    fn test_tiered_cost_above_last_bound() {
+        const TIERS: &[RateTier] = &[RateTier { up_to_kwh: Some(120), rate: 29_80 }, RateTier { up_to_kwh: Some(300), rate: 36_40 }];
+        let tariff = Tariff {
+            basic_charge: 0,
+            energy_rate: EnergyRate::Tiered(TIERS),
+            fuel_adjustment: 0,
+            renewable_surcharge: 0,
+        };
+        let mut calculator = CostCalculator::new(JST);
+        calculator.push(&reading("2022-10-01T00:00:00Z", 1000));
+        calculator.push(&reading("2022-10-31T00:00:00Z", 1350));
+        assert_eq!(calculator.cost(&tariff).energy_charge, 120 * 29_80 + 230 * 36_40);
+    }
+
+    #[test]
+    fn test_time_of_use_cost() {
+        // Night rate from 23:00 to 7:00 JST, day rate otherwise.
+        const BANDS: &[TimeOfUseBand] = &[
+            TimeOfUseBand { start_hour: 23, end_hour: 7, rate: 20_00 },
+            TimeOfUseBand { start_hour: 7, end_hour: 23, rate: 30_00 },
+        ];
+        let tariff = Tariff {
+            basic_charge: 0,
+            energy_rate: EnergyRate::TimeOfUse(BANDS),
+            fuel_adjustment: 0,
+            renewable_surcharge: 0,
+        };
+        let mut calculator = CostCalculator::new(JST);
+        // 21:00 to 01:00 JST, 1 kWh per hour.
+        calculator.push(&reading("2022-10-01T12:00:00Z", 0));
+        calculator.push(&reading("2022-10-01T16:00:00Z", 4));
+        assert_eq!(calculator.cost(&tariff).energy_charge, 2 * 30_00 + 2 * 20_00);
+    }
+
+    #[test]
+    fn test_flat_cost_without_drift() {
+        let tariff = Tariff {
+            basic_charge: 0,
+            energy_rate: EnergyRate::Flat(33_33),
+            fuel_adjustment: 0,
+            renewable_surcharge: 0,
+        };
+        let mut calculator = CostCalculator::new(0);
+        let start = Timestamp::from_str("2022-10-01T00:00:00Z").unwrap();
+        // 1 Wh per minute over 30 days.
+        for minute in 0..(30 * 24 * 60) {
+            calculator.push(&SmartMeterReading {
+                updated_at: start + chrono::Duration::minutes(minute),
+                cumulative_energy_mwh: minute as u64 * 1000,
+                instantaneous_power: None,
+                modulus_mwh: None,
+            });
+        }
+        // 43.199 kWh * 33.33 yen/kWh = 1439.82267 yen
+        assert_eq!(calculator.cost(&tariff).energy_charge, 1439_82);
+    }
+}
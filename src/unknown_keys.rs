@@ -0,0 +1,85 @@
+// Reporting of keys unknown to the parsers.
+// Copyright 2022-2023 Kenta Ida 
+// SPDX-License-Identifier: MIT
+//
+use core::fmt;
+
+use heapless::{String, Vec};
+
+use crate::config::MAX_ERROR_PATH_DEPTH;
+use crate::diagnostics::BoundedSink;
+use crate::location::{write_path, PathSegment};
+use crate::parser_options::{copy_string_possible, TruncationMode};
+
+/// Why a key is not parsed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UnknownKeyKind {
+    /// The key is not known by the parsers, e.g. a field added to the API.
+    Unknown,
+    /// The key is in the Remo API but the parsers do not model it, e.g. `aircon` of an appliance.
+    /// Its value is skipped, so the keys in it are not reported.
+    Unmodeled,
+}
+
+/// Receives the keys not known by the parsers. See `ParserOptions::with_unknown_keys`.
+pub trait UnknownKeySink {
+    /// `path` is the JSON path of the map which contains `key`.
+    fn report(&self, path: &[PathSegment], key: &str, kind: UnknownKeyKind);
+}
+
+/// Ignores all unknown keys.
+impl UnknownKeySink for () {
+    fn report(&self, _path: &[PathSegment], _key: &str, _kind: UnknownKeyKind) {}
+}
+
+/// An unknown key and the path of the map which contains it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownKey<const L: usize> {
+    /// Path at the first occurrence of the key.
+    pub path: Vec<PathSegment, MAX_ERROR_PATH_DEPTH>,
+    /// The key, truncated to `L` bytes.
+    pub key: String<L>,
+    /// Whether the key is unknown or in the API but not modeled.
+    pub kind: UnknownKeyKind,
+}
+
+impl<const L: usize> UnknownKey<L> {
+    /// Whether `path` and `key` are the same field as this one, ignoring the indices of the arrays.
+    fn is_same_field(&self, path: &[PathSegment], key: &str) -> bool {
+        self.key.as_str() == key
+            && self.path.len() == path.len()
+            && self.path.iter().zip(path).all(|segment| match segment {
+                (PathSegment::Index(_), PathSegment::Index(_)) => true,
+                (a, b) => a == b,
+            })
+    }
+}
+
+/// Format as `<path>.<key>`, e.g. `[0].device.new_field`.
+impl<const L: usize> fmt::Display for UnknownKey<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_path(f, &self.path)?;
+        let separator = if self.path.is_empty() { "" } else { "." };
+        write!(f, "{}{}", separator, self.key)
+    }
+}
+
+/// Keeps the first `N` distinct unknown keys of up to `L` bytes and counts the rest.
+/// Keys at the same path in different elements of an array are kept once,
+/// but repeated reports of a dropped key are counted each time.
+pub type UnknownKeys<const N: usize, const L: usize = 32> = BoundedSink<UnknownKey<L>, N>;
+
+impl<const N: usize, const L: usize> UnknownKeySink for UnknownKeys<N, L> {
+    fn report(&self, path: &[PathSegment], key: &str, kind: UnknownKeyKind) {
+        let key = copy_string_possible::<L>(key, TruncationMode::Char);
+        if self.any(|entry| entry.is_same_field(path, &key)) {
+            return;
+        }
+        self.push(UnknownKey {
+            path: path.iter().copied().take(MAX_ERROR_PATH_DEPTH).collect(),
+            key,
+            kind,
+        });
+    }
+}
@@ -0,0 +1,572 @@
+// JSON writer to re-emit parsed models in the shape of Remo Cloud API.
+// Copyright 2022-2023 Kenta Ida 
+// SPDX-License-Identifier: MIT
+//
+use chrono::{Datelike, Timelike};
+use embedded_io::blocking::{Write, WriteFmtError};
+
+use crate::appliances::*;
+use crate::common_types::*;
+use crate::config::Capacities;
+use crate::device::*;
+
+/// Error of the JSON writers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JsonWriteError<E> {
+    /// Failed to write into the underlying writer.
+    Write(WriteFmtError<E>),
+    /// The callbacks were not given in the order of the document, e.g. a user without its device.
+    InvalidState,
+}
+
+impl<E> From<WriteFmtError<E>> for JsonWriteError<E> {
+    fn from(err: WriteFmtError<E>) -> Self {
+        Self::Write(err)
+    }
+}
+
+fn write_raw<W: Write>(writer: &mut W, s: &str) -> Result<(), JsonWriteError<W::Error>> {
+    writer.write_all(s.as_bytes()).map_err(|err| JsonWriteError::Write(WriteFmtError::Other(err)))
+}
+
+fn write_string<W: Write>(writer: &mut W, s: &str) -> Result<(), JsonWriteError<W::Error>> {
+    write_raw(writer, "\"")?;
+    let mut rest = s;
+    while let Some(index) = rest.find(|c: char| c == '"' || c == '\\' || c < ' ') {
+        write_raw(writer, &rest[..index])?;
+        let c = rest[index..].chars().next().unwrap_or_default();
+        match c {
+            '"' => write_raw(writer, "\\\"")?,
+            '\\' => write_raw(writer, "\\\\")?,
+            '\n' => write_raw(writer, "\\n")?,
+            '\r' => write_raw(writer, "\\r")?,
+            '\t' => write_raw(writer, "\\t")?,
+            c => write!(writer, "\\u{:04x}", c as u32)?,
+        }
+        rest = &rest[index + c.len_utf8()..];
+    }
+    write_raw(writer, rest)?;
+    write_raw(writer, "\"")
+}
+
+/// Writes members of a JSON map, inserting commas between them.
+struct MapWriter<'a, W: Write> {
+    writer: &'a mut W,
+    has_member: bool,
+}
+
+impl<'a, W: Write> MapWriter<'a, W> {
+    fn begin(writer: &'a mut W) -> Result<Self, JsonWriteError<W::Error>> {
+        write_raw(writer, "{")?;
+        Ok(Self::resume(writer, false))
+    }
+    fn resume(writer: &'a mut W, has_member: bool) -> Self {
+        Self { writer, has_member }
+    }
+    fn key(&mut self, key: &str) -> Result<&mut W, JsonWriteError<W::Error>> {
+        if self.has_member {
+            write_raw(self.writer, ",")?;
+        }
+        self.has_member = true;
+        write_string(self.writer, key)?;
+        write_raw(self.writer, ":")?;
+        Ok(self.writer)
+    }
+    fn string(&mut self, key: &str, value: &str) -> Result<(), JsonWriteError<W::Error>> {
+        let writer = self.key(key)?;
+        write_string(writer, value)
+    }
+    fn display(&mut self, key: &str, value: impl core::fmt::Display) -> Result<(), JsonWriteError<W::Error>> {
+        let writer = self.key(key)?;
+        Ok(write!(writer, "\"{}\"", value)?)
+    }
+    fn number(&mut self, key: &str, value: f32) -> Result<(), JsonWriteError<W::Error>> {
+        let writer = self.key(key)?;
+        if value.is_finite() {
+            Ok(write!(writer, "{}", value)?)
+        } else {
+            write_raw(writer, "null")
+        }
+    }
+    fn timestamp(&mut self, key: &str, value: &Timestamp) -> Result<(), JsonWriteError<W::Error>> {
+        let writer = self.key(key)?;
+        write!(
+            writer,
+            "\"{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            value.year(),
+            value.month(),
+            value.day(),
+            value.hour(),
+            value.minute(),
+            value.second()
+        )?;
+        if value.nanosecond() != 0 {
+            write!(writer, ".{:09}", value.nanosecond())?;
+        }
+        write_raw(writer, "Z\"")
+    }
+    fn end(self) -> Result<(), JsonWriteError<W::Error>> {
+        write_raw(self.writer, "}")
+    }
+}
+
+fn write_device_members<W: Write, C: Capacities>(map: &mut MapWriter<W>, device: &Device<C>) -> Result<(), JsonWriteError<W::Error>> {
+    map.string("name", &device.name)?;
+    map.display("id", device.id)?;
+    map.timestamp("created_at", &device.created_at)?;
+    map.timestamp("updated_at", &device.updated_at)?;
+    map.display("mac_address", device.mac_address)?;
+    map.display("bt_mac_address", device.bt_mac_address)?;
+    map.string("serial_number", &device.serial_number)?;
+    map.string("firmware_version", &device.firmware_version)?;
+    map.number("temperature_offset", device.temperature_offset)?;
+    map.number("humidity_offset", device.humidity_offset)
+}
+
+/// Write a device map without `users` and `newest_events`, as in the `device` node of an appliance.
+pub fn write_device<W: Write, C: Capacities>(writer: &mut W, device: &Device<C>) -> Result<(), JsonWriteError<W::Error>> {
+    let mut map = MapWriter::begin(writer)?;
+    write_device_members(&mut map, device)?;
+    map.end()
+}
+
+pub fn write_user<W: Write, C: Capacities>(writer: &mut W, user: &User<C>) -> Result<(), JsonWriteError<W::Error>> {
+    let mut map = MapWriter::begin(writer)?;
+    map.display("id", user.id)?;
+    map.string("nickname", &user.nickname)?;
+    let writer = map.key("superuser")?;
+    write_raw(writer, if user.superuser { "true" } else { "false" })?;
+    map.end()
+}
+
+/// Write a `newest_events` map. Missing sensors are omitted.
+pub fn write_newest_events<W: Write>(writer: &mut W, events: &NewestEvents) -> Result<(), JsonWriteError<W::Error>> {
+    let mut map = MapWriter::begin(writer)?;
+    let sensors = [
+        ("te", &events.temperature),
+        ("hu", &events.humidity),
+        ("il", &events.illumination),
+        ("mo", &events.motion),
+    ];
+    for (key, value) in sensors {
+        if let Some(value) = value {
+            let mut sensor = MapWriter::begin(map.key(key)?)?;
+            sensor.number("val", value.val)?;
+            sensor.timestamp("created_at", &value.created_at)?;
+            sensor.end()?;
+        }
+    }
+    map.end()
+}
+
+pub fn write_appliance_model<W: Write, C: Capacities>(writer: &mut W, model: &ApplianceModel<C>) -> Result<(), JsonWriteError<W::Error>> {
+    let mut map = MapWriter::begin(writer)?;
+    map.display("id", model.id)?;
+    map.string("country", &model.country)?;
+    map.string("manufacturer", &model.manufacturer)?;
+    map.string("remote_name", &model.remote_name)?;
+    map.string("series", &model.series)?;
+    map.string("name", &model.name)?;
+    map.string("image", &model.image)?;
+    map.end()
+}
+
+pub fn write_echonet_lite_property<W: Write, C: Capacities>(
+    writer: &mut W,
+    property: &EchonetLiteProperty<C>,
+) -> Result<(), JsonWriteError<W::Error>> {
+    let mut map = MapWriter::begin(writer)?;
+    map.string("name", &property.name)?;
+    write!(map.key("epc")?, "{}", property.epc)?;
+    map.string("val", &property.val)?;
+    map.timestamp("updated_at", &property.updated_at)?;
+    map.end()
+}
+
+fn write_appliance_members<W: Write, C: Capacities>(
+    map: &mut MapWriter<W>,
+    appliance: &Appliance<C>,
+    with_id: bool,
+) -> Result<(), JsonWriteError<W::Error>> {
+    if with_id {
+        map.display("id", appliance.id)?;
+    }
+    map.string("type", appliance.type_.as_str())?;
+    map.string("nickname", &appliance.nickname)?;
+    map.string("image", &appliance.image)
+}
+
+/// Write an appliance map with its sub nodes at once.
+/// `smart_meter` is written only if `properties` is not empty.
+pub fn write_appliance<W: Write, C: Capacities>(
+    writer: &mut W,
+    appliance: &Appliance<C>,
+    device: Option<&Device<C>>,
+    model: Option<&ApplianceModel<C>>,
+    properties: &[EchonetLiteProperty<C>],
+) -> Result<(), JsonWriteError<W::Error>> {
+    let mut map = MapWriter::begin(writer)?;
+    write_appliance_members(&mut map, appliance, true)?;
+    if let Some(device) = device {
+        write_device(map.key("device")?, device)?;
+    }
+    if let Some(model) = model {
+        write_appliance_model(map.key("model")?, model)?;
+    }
+    if !properties.is_empty() {
+        let mut smart_meter = MapWriter::begin(map.key("smart_meter")?)?;
+        let writer = smart_meter.key("echonetlite_properties")?;
+        write_raw(writer, "[")?;
+        for (index, property) in properties.iter().enumerate() {
+            if index > 0 {
+                write_raw(writer, ",")?;
+            }
+            write_echonet_lite_property(writer, property)?;
+        }
+        write_raw(writer, "]")?;
+        smart_meter.end()?;
+    }
+    map.end()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DevicesWriterState {
+    Start,
+    /// Between devices.
+    Array,
+    /// Device map is open and its `users` array is open.
+    Users { has_user: bool },
+    /// Device map is open.
+    Device,
+}
+
+/// Writes a `/1/devices` document from the callbacks of `read_devices`.
+///
+/// The callbacks are expected in the order of the Cloud API document, i.e. `users` precedes `newest_events`.
+/// Callbacks can be skipped to filter devices or sub nodes.
+pub struct DevicesWriter<'a, W: Write> {
+    writer: &'a mut W,
+    state: DevicesWriterState,
+}
+
+impl<'a, W: Write> DevicesWriter<'a, W> {
+    pub fn new(writer: &'a mut W) -> Self {
+        Self {
+            writer,
+            state: DevicesWriterState::Start,
+        }
+    }
+
+    fn close_device(&mut self) -> Result<(), JsonWriteError<W::Error>> {
+        match self.state {
+            DevicesWriterState::Start => write_raw(self.writer, "[")?,
+            DevicesWriterState::Array => {}
+            DevicesWriterState::Users { .. } => write_raw(self.writer, "]}")?,
+            DevicesWriterState::Device => write_raw(self.writer, "}")?,
+        }
+        self.state = DevicesWriterState::Array;
+        Ok(())
+    }
+
+    fn open_device<C: Capacities>(&mut self, device: &Device<C>) -> Result<(), JsonWriteError<W::Error>> {
+        let has_device = self.state != DevicesWriterState::Start;
+        self.close_device()?;
+        if has_device {
+            write_raw(self.writer, ",")?;
+        }
+        let mut map = MapWriter::begin(&mut *self.writer)?;
+        write_device_members(&mut map, device)?;
+        self.state = DevicesWriterState::Device;
+        Ok(())
+    }
+
+    /// Write a callback of `read_devices`.
+    pub fn write<C: Capacities>(&mut self, device: &Device<C>, sub_node: Option<&DeviceSubNode<C>>) -> Result<(), JsonWriteError<W::Error>> {
+        match sub_node {
+            None => {
+                self.open_device(device)?;
+                MapWriter::resume(&mut *self.writer, true).key("users")?;
+                write_raw(self.writer, "[")?;
+                self.state = DevicesWriterState::Users { has_user: false };
+            }
+            Some(DeviceSubNode::User(user)) => {
+                match self.state {
+                    DevicesWriterState::Users { has_user: true } => write_raw(self.writer, ",")?,
+                    DevicesWriterState::Users { has_user: false } => {}
+                    // A user must follow the callback of its device.
+                    _ => return Err(JsonWriteError::InvalidState),
+                }
+                write_user(self.writer, user)?;
+                self.state = DevicesWriterState::Users { has_user: true };
+            }
+            Some(DeviceSubNode::NewestEvents(events)) => {
+                match self.state {
+                    DevicesWriterState::Users { .. } => write_raw(self.writer, "]")?,
+                    DevicesWriterState::Device => {}
+                    DevicesWriterState::Start | DevicesWriterState::Array => self.open_device(device)?,
+                }
+                write_newest_events(MapWriter::resume(&mut *self.writer, true).key("newest_events")?, events)?;
+                self.state = DevicesWriterState::Device;
+            }
+        }
+        Ok(())
+    }
+
+    /// Close the document.
+    pub fn finish(mut self) -> Result<(), JsonWriteError<W::Error>> {
+        self.close_device()?;
+        write_raw(self.writer, "]")
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AppliancesWriterState {
+    Start,
+    /// Between appliances.
+    Array,
+    /// Appliance map is open.
+    Appliance { has_member: bool, has_id: bool },
+    /// `echonetlite_properties` array in the `smart_meter` map of the appliance is open.
+    Properties { has_property: bool, has_id: bool },
+}
+
+/// Writes a `/1/appliances` document from the callbacks of `read_appliances`.
+///
+/// Sub nodes are written as they arrive, and the members of the appliance are written at the end of its map,
+/// except `id` which is written before the sub nodes if it has been parsed as in the Cloud API document.
+/// Sub node callbacks can be skipped to filter them. To filter appliances by their members, e.g. the type,
+/// keep the sub nodes until the appliance callback and use `write_appliance` instead.
+pub struct AppliancesWriter<'a, W: Write> {
+    writer: &'a mut W,
+    state: AppliancesWriterState,
+}
+
+impl<'a, W: Write> AppliancesWriter<'a, W> {
+    pub fn new(writer: &'a mut W) -> Self {
+        Self {
+            writer,
+            state: AppliancesWriterState::Start,
+        }
+    }
+
+    /// Open an appliance map if not yet, and close the `smart_meter` map if open.
+    /// Returns the writer of the appliance map and whether `id` has been written.
+    fn appliance_map<C: Capacities>(&mut self, appliance: &Appliance<C>) -> Result<(MapWriter<'_, W>, bool), JsonWriteError<W::Error>> {
+        let (map, has_id) = match self.state {
+            AppliancesWriterState::Start | AppliancesWriterState::Array => {
+                let start = if self.state == AppliancesWriterState::Start { "[" } else { "," };
+                write_raw(self.writer, start)?;
+                let mut map = MapWriter::begin(&mut *self.writer)?;
+                let has_id = !appliance.id.is_nil();
+                if has_id {
+                    map.display("id", appliance.id)?;
+                }
+                (map, has_id)
+            }
+            AppliancesWriterState::Appliance { has_member, has_id } => (MapWriter::resume(&mut *self.writer, has_member), has_id),
+            AppliancesWriterState::Properties { has_id, .. } => {
+                write_raw(self.writer, "]}")?;
+                (MapWriter::resume(&mut *self.writer, true), has_id)
+            }
+        };
+        // The caller writes a member next.
+        self.state = AppliancesWriterState::Appliance { has_member: true, has_id };
+        Ok((map, has_id))
+    }
+
+    /// Write a callback of `read_appliances`.
+    pub fn write<C: Capacities>(&mut self, appliance: &Appliance<C>, sub_node: Option<&ApplianceSubNode<C>>) -> Result<(), JsonWriteError<W::Error>> {
+        match sub_node {
+            Some(ApplianceSubNode::Device(device)) => write_device(self.appliance_map(appliance)?.0.key("device")?, device),
+            Some(ApplianceSubNode::Model(model)) => {
+                write_appliance_model(self.appliance_map(appliance)?.0.key("model")?, model)
+            }
+            Some(ApplianceSubNode::EchonetLiteProperty(property)) => {
+                let (has_property, has_id) = match self.state {
+                    AppliancesWriterState::Properties { has_property, has_id } => (has_property, has_id),
+                    _ => {
+                        let (mut map, has_id) = self.appliance_map(appliance)?;
+                        write_raw(map.key("smart_meter")?, "{\"echonetlite_properties\":[")?;
+                        (false, has_id)
+                    }
+                };
+                if has_property {
+                    write_raw(self.writer, ",")?;
+                }
+                write_echonet_lite_property(self.writer, property)?;
+                self.state = AppliancesWriterState::Properties { has_property: true, has_id };
+                Ok(())
+            }
+            None => {
+                let (mut map, has_id) = self.appliance_map(appliance)?;
+                write_appliance_members(&mut map, appliance, !has_id)?;
+                map.end()?;
+                self.state = AppliancesWriterState::Array;
+                Ok(())
+            }
+        }
+    }
+
+    /// Close the document.
+    pub fn finish(self) -> Result<(), JsonWriteError<W::Error>> {
+        match self.state {
+            AppliancesWriterState::Start => write_raw(self.writer, "[")?,
+            AppliancesWriterState::Array => {}
+            AppliancesWriterState::Appliance { .. } => write_raw(self.writer, "}")?,
+            AppliancesWriterState::Properties { .. } => write_raw(self.writer, "]}}")?,
+        }
+        write_raw(self.writer, "]")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::str::FromStr;
+
+    use fuga_json_seq_parser::{BufferReader, ParserError as JsonParserError};
+    use heapless::Vec;
+
+    use super::*;
+    use crate::parser_options::ParserOptions;
+
+    /// Write into the buffer and return the written string.
+    fn write_to(buffer: &mut [u8], f: impl FnOnce(&mut &mut [u8])) -> &str {
+        let length = buffer.len();
+        let mut writer = &mut buffer[..];
+        f(&mut writer);
+        let remaining = writer.len();
+        core::str::from_utf8(&buffer[..length - remaining]).unwrap()
+    }
+
+    #[test]
+    fn test_write_string_escape() {
+        let mut buffer = [0u8; 64];
+        let s = write_to(&mut buffer, |w| write_string(w, "a\"b\\c\nd\u{1}").unwrap());
+        assert_eq!(s, r#""a\"b\\c\nd\u0001""#);
+    }
+
+    #[test]
+    fn test_write_appliance() {
+        let appliance: Appliance = Appliance {
+            id: uuid::uuid!("84875896-9f1e-44df-9f49-7989352eeecf"),
+            type_: ApplianceType::SmartMeter,
+            nickname: heapless::String::from("meter"),
+            image: heapless::String::from("ico_smartmeter"),
+        };
+        let property = EchonetLiteProperty {
+            name: heapless::String::from("cumulative_electric_energy_unit"),
+            epc: 225,
+            val: heapless::String::from("2"),
+            updated_at: Timestamp::from_str("2022-10-22T11:38:14Z").unwrap(),
+        };
+        let mut buffer = [0u8; 512];
+        let s = write_to(&mut buffer, |w| write_appliance(w, &appliance, None, None, &[property]).unwrap());
+        assert_eq!(
+            s,
+            r#"{"id":"84875896-9f1e-44df-9f49-7989352eeecf","type":"EL_SMART_METER","nickname":"meter","image":"ico_smartmeter","smart_meter":{"echonetlite_properties":[{"name":"cumulative_electric_energy_unit","epc":225,"val":"2","updated_at":"2022-10-22T11:38:14Z"}]}}"#
+        );
+    }
+
+    #[test]
+    fn test_reemit_unknown_appliance_type() {
+        let input = r#"[{"type":"NEW_APPLIANCE","nickname":"new"}]"#;
+        let diagnostics = crate::diagnostics::Diagnostics::<1>::new();
+        let options = ParserOptions::default().lenient(&diagnostics);
+        let mut buffer = [0u8; 256];
+        let output = write_to(&mut buffer, |w| {
+            let mut writer = AppliancesWriter::new(w);
+            read_appliances(&mut BufferReader::new(input.as_bytes()), Some(input.len()), &options, |appliance, sub_node| {
+                writer.write(appliance, sub_node).unwrap();
+            })
+            .unwrap();
+            writer.finish().unwrap();
+        });
+        assert!(output.contains(r#""type":"UNKNOWN""#));
+        // `UNKNOWN` is not a type of the API, so it is read back as unknown again only in lenient mode.
+        let error = read_appliances(&mut BufferReader::new(output.as_bytes()), Some(output.len()), &ParserOptions::default(), |_, _| {}).unwrap_err();
+        assert!(matches!(error.error, JsonParserError::Callback(ModelNodeParseError::UnexpectedEnumValue)));
+        diagnostics.take();
+        let mut types: Vec<ApplianceType, 1> = Vec::new();
+        read_appliances(&mut BufferReader::new(output.as_bytes()), Some(output.len()), &options, |appliance, _| {
+            types.push(appliance.type_).unwrap();
+        })
+        .unwrap();
+        assert_eq!(types.as_slice(), &[ApplianceType::Unknown]);
+        assert_eq!(diagnostics.len(), 1);
+    }
+
+    /// Format a callback of `read_devices` to compare, as `DeviceSubNode` does not implement `PartialEq`.
+    fn format_device_callback(device: &Device, sub_node: Option<&DeviceSubNode>) -> heapless::String<1024> {
+        let mut s = heapless::String::new();
+        core::fmt::Write::write_fmt(&mut s, format_args!("{:?} {:?}", device, sub_node)).unwrap();
+        s
+    }
+
+    #[test]
+    fn test_reemit_devices() {
+        let input = include_str!("../data/devices.json");
+        let mut expected: Vec<heapless::String<1024>, 16> = Vec::new();
+        let mut buffer = [0u8; 4096];
+        let output = write_to(&mut buffer, |w| {
+            let mut writer = DevicesWriter::new(w);
+            read_devices(&mut BufferReader::new(input.as_bytes()), Some(input.len()), &ParserOptions::default(), |device, sub_node| {
+                writer.write(device, sub_node).unwrap();
+                expected.push(format_device_callback(device, sub_node)).unwrap();
+            })
+            .unwrap();
+            writer.finish().unwrap();
+        });
+        let mut index = 0;
+        read_devices(&mut BufferReader::new(output.as_bytes()), Some(output.len()), &ParserOptions::default(), |device, sub_node| {
+            assert_eq!(format_device_callback(device, sub_node), expected[index]);
+            index += 1;
+        })
+        .unwrap();
+        assert_eq!(index, expected.len());
+    }
+
+    #[test]
+    fn test_reemit_appliances() {
+        let input = include_str!("../data/appliances.json");
+        let mut expected: Vec<(Appliance, Option<ApplianceSubNode>), 32> = Vec::new();
+        let mut buffer = [0u8; 8192];
+        let output = write_to(&mut buffer, |w| {
+            let mut writer = AppliancesWriter::new(w);
+            read_appliances(&mut BufferReader::new(input.as_bytes()), Some(input.len()), &ParserOptions::default(), |appliance, sub_node| {
+                writer.write(appliance, sub_node).unwrap();
+                expected.push((appliance.clone(), sub_node.cloned())).unwrap();
+            })
+            .unwrap();
+            writer.finish().unwrap();
+        });
+        let mut index = 0;
+        read_appliances(&mut BufferReader::new(output.as_bytes()), Some(output.len()), &ParserOptions::default(), |appliance, sub_node| {
+            // Members of the appliance except `id` are written at the end of its map.
+            match sub_node {
+                Some(_) => assert_eq!(appliance.id, expected[index].0.id),
+                None => assert_eq!(appliance, &expected[index].0),
+            }
+            assert_eq!(sub_node, expected[index].1.as_ref());
+            index += 1;
+        })
+        .unwrap();
+        assert_eq!(index, expected.len());
+    }
+
+    #[test]
+    fn test_empty_documents() {
+        let mut buffer = [0u8; 16];
+        assert_eq!(write_to(&mut buffer, |w| DevicesWriter::new(w).finish().unwrap()), "[]");
+        assert_eq!(write_to(&mut buffer, |w| AppliancesWriter::new(w).finish().unwrap()), "[]");
+    }
+
+    #[test]
+    fn test_user_without_device() {
+        let mut buffer = [0u8; 16];
+        write_to(&mut buffer, |w| {
+            let device: Device = Device::default();
+            let result = DevicesWriter::new(w).write(&device, Some(&DeviceSubNode::User(User::default())));
+            assert!(matches!(result, Err(JsonWriteError::InvalidState)));
+        });
+    }
+}